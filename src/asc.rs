@@ -0,0 +1,84 @@
+//! ASC→ strings
+//!
+//! Objects shared as text are written by →ASC as a string of hexadecimal
+//! digits, one per nibble in memory order, followed by the 4 nibbles of the
+//! CRC of the object. The ASC→ program checks the CRC and rebuilds the object.
+use winnow::Parser;
+
+use crate::encode::to_nibbles;
+use crate::nibbles::{hp_crc, Nibbles};
+use crate::{next_obj, Error, Obj};
+
+// digits per line, as written by →ASC
+const LINE_WIDTH: usize = 64;
+
+/// ASC→ string of an object, quotes included
+pub fn to_asc(obj: &Obj) -> String {
+    let mut nibs = to_nibbles(obj);
+    let crc = hp_crc(&nibs);
+    nibs.extend((0..4).map(|i| ((crc >> (4 * i)) & 0xf) as u8));
+    let digits: Vec<char> = nibs.iter().map(|n| char::from_digit(*n as u32, 16).unwrap().to_ascii_uppercase()).collect();
+    let lines: Vec<String> = digits.chunks(LINE_WIDTH).map(|line| line.iter().collect()).collect();
+    format!("\"{}\"", lines.join("\n"))
+}
+
+/// Object of an ASC→ string. The quotes and a `%%HP:` header are optional,
+/// whitespace is ignored
+pub fn from_asc(text: &str) -> Result<Obj, Error> {
+    let mut text = text.trim();
+    if text.starts_with("%%HP:") {
+        text = text.split_once(';').map_or("", |(_, body)| body).trim();
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        text = inner;
+    }
+    let mut nibs = Vec::new();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        let digit = c.to_digit(16).ok_or_else(|| Error::ParseError(format!("{:?} is not a hexadecimal digit", c)))?;
+        nibs.push(digit as u8);
+    }
+    if nibs.len() < 9 {
+        return Err(Error::ParseError("ASC→ string too short".to_string()));
+    }
+    let (body, crc_nibs) = nibs.split_at(nibs.len() - 4);
+    let crc = crc_nibs.iter().rev().fold(0u16, |acc, n| (acc << 4) | *n as u16);
+    if crc != hp_crc(body) {
+        return Err(Error::ParseError(format!("ASC→ checksum {:04X} does not match {:04X}", crc, hp_crc(body))));
+    }
+    next_obj.parse(Nibbles::new(body)).map_err(|e| Error::ParseError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Extable, StringBlob};
+    use crate::decompile::Decompiled;
+
+    #[test]
+    fn test_asc() {
+        let obj = Obj::Prg(vec![Obj::Bint(1), Obj::CStr(StringBlob("AB".to_string()))]);
+        let asc = to_asc(&obj);
+        // DOCOL, DOBINT 1, DOCSTR "AB", SEMI
+        assert!(asc.starts_with("\"D9D201192010000C2A20900001424B2130"));
+        let back = from_asc(&format!("%%HP: T(3)A(R)F(.);\r\n{}\r\n", asc)).unwrap();
+        assert_eq!(back.decompile(&Extable::default()), obj.decompile(&Extable::default()));
+    }
+
+    #[test]
+    fn test_lines() {
+        let obj = Obj::CStr(StringBlob("x".repeat(40)));
+        let asc = to_asc(&obj);
+        let lines: Vec<&str> = asc.trim_matches('"').lines().collect();
+        assert_eq!(lines.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![64, 30]);
+        assert!(from_asc(&asc).is_ok());
+    }
+
+    #[test]
+    fn test_errors() {
+        let asc = to_asc(&Obj::Bint(1));
+        let corrupted = asc.replacen("1", "2", 1);
+        assert!(from_asc(&corrupted).unwrap_err().to_string().contains("checksum"));
+        assert!(from_asc("\"D9D2G\"").is_err());
+        assert!(from_asc("\"1234\"").is_err());
+    }
+}
@@ -0,0 +1,583 @@
+//! Saturn assembler
+//!
+//! Assembles MASD source into a code object, one instruction per line.
+//! The HP syntax written by the disassembler is accepted too, so that its output
+//! assembles back to the same nibbles.
+//!
+//! ```text
+//! *LOOP
+//!   GOSBVL =SAVPTR
+//!   ?A#0.A GOYES LOOP
+//!   $1A       % data
+//! ```
+//!
+//! Labels are `*name`, or a name alone in the first column. `%` starts a comment.
+use std::collections::HashMap;
+
+use crate::disasm::{AA, AB, BA, BB, DATA, D_REGS, FIELDS, LOGIC, REGS, SHIFTS, TEST_CMP, TEST_EQ};
+use crate::{Blob, Error, Extable, Obj};
+
+// instructions without operand
+const SIMPLE: &[(&str, &[u8])] = &[
+    ("RTNSXM", &[0, 0]),
+    ("RTN", &[0, 1]),
+    ("RTNSC", &[0, 2]),
+    ("RTNCC", &[0, 3]),
+    ("SETHEX", &[0, 4]),
+    ("SETDEC", &[0, 5]),
+    ("RSTK=C", &[0, 6]),
+    ("C=RSTK", &[0, 7]),
+    ("CLRST", &[0, 8]),
+    ("C=ST", &[0, 9]),
+    ("ST=C", &[0, 0xa]),
+    ("CSTEX", &[0, 0xb]),
+    ("P=P+1", &[0, 0xc]),
+    ("P=P-1", &[0, 0xd]),
+    ("RTI", &[0, 0xf]),
+    ("RTNC", &[4, 0, 0]),
+    ("RTNNC", &[5, 0, 0]),
+    ("NOP3", &[4, 2, 0]),
+    ("NOP4", &[6, 3, 0, 0]),
+    ("NOP5", &[6, 4, 0, 0, 0]),
+    ("OUT=CS", &[8, 0, 0]),
+    ("OUT=C", &[8, 0, 1]),
+    ("A=IN", &[8, 0, 2]),
+    ("C=IN", &[8, 0, 3]),
+    ("UNCNFG", &[8, 0, 4]),
+    ("CONFIG", &[8, 0, 5]),
+    ("C=ID", &[8, 0, 6]),
+    ("SHUTDN", &[8, 0, 7]),
+    ("INTON", &[8, 0, 8, 0]),
+    ("RSI", &[8, 0, 8, 1, 0]),
+    ("BUSCB", &[8, 0, 8, 3]),
+    ("PC=(A)", &[8, 0, 8, 0xc]),
+    ("BUSCD", &[8, 0, 8, 0xd]),
+    ("PC=(C)", &[8, 0, 8, 0xe]),
+    ("INTOFF", &[8, 0, 8, 0xf]),
+    ("C=C+P+1", &[8, 0, 9]),
+    ("RESET", &[8, 0, 0xa]),
+    ("BUSCC", &[8, 0, 0xb]),
+    ("SREQ?", &[8, 0, 0xe]),
+    ("PC=A", &[8, 1, 0xb, 2]),
+    ("PC=C", &[8, 1, 0xb, 3]),
+    ("A=PC", &[8, 1, 0xb, 4]),
+    ("C=PC", &[8, 1, 0xb, 5]),
+    ("APCEX", &[8, 1, 0xb, 6]),
+    ("CPCEX", &[8, 1, 0xb, 7]),
+    ("XM=0", &[8, 2, 1]),
+    ("SB=0", &[8, 2, 2]),
+    ("SR=0", &[8, 2, 4]),
+    ("MP=0", &[8, 2, 8]),
+    ("CLRHST", &[8, 2, 0xf]),
+];
+
+// tests without operand
+const SIMPLE_TESTS: &[(&str, &[u8])] =
+    &[("?XM=0", &[8, 3, 1]), ("?SB=0", &[8, 3, 2]), ("?SR=0", &[8, 3, 4]), ("?MP=0", &[8, 3, 8])];
+
+// instructions with a decimal operand, and whether they are tests
+const COUNTS: &[(&str, &[u8], bool)] = &[
+    ("ST=0", &[8, 4], false),
+    ("ST=1", &[8, 5], false),
+    ("HST=0", &[8, 2], false),
+    ("ABIT=0", &[8, 0, 8, 4], false),
+    ("ABIT=1", &[8, 0, 8, 5], false),
+    ("CBIT=0", &[8, 0, 8, 8], false),
+    ("CBIT=1", &[8, 0, 8, 9], false),
+    ("C=P", &[8, 0, 0xc], false),
+    ("P=C", &[8, 0, 0xd], false),
+    ("CPEX", &[8, 0, 0xf], false),
+    ("?ST=0", &[8, 6], true),
+    ("?ST=1", &[8, 7], true),
+    ("?P#", &[8, 8], true),
+    ("?P=", &[8, 9], true),
+    ("?ABIT=0", &[8, 0, 8, 6], true),
+    ("?ABIT=1", &[8, 0, 8, 7], true),
+    ("?CBIT=0", &[8, 0, 8, 0xa], true),
+    ("?CBIT=1", &[8, 0, 8, 0xb], true),
+    ("?HST=0", &[8, 3], true),
+];
+
+// relative jumps: opcode, offset width, distance from the offset to the origin
+const JUMPS: &[(&str, &[u8], usize, usize)] = &[
+    ("GOC", &[4], 2, 0),
+    ("GONC", &[5], 2, 0),
+    ("GOTO", &[6], 3, 0),
+    ("GOSUB", &[7], 3, 3),
+    ("GOLONG", &[8, 0xc], 4, 0),
+    ("GOSUBL", &[8, 0xe], 4, 4),
+];
+
+#[derive(Debug, Clone)]
+enum Target {
+    Label(String),
+    /// `*+n`, relative to the instruction
+    Relative(isize),
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Nibs(Vec<u8>),
+    Offset { width: usize, origin: usize, target: Target },
+}
+
+struct Instruction {
+    pieces: Vec<Piece>,
+    /// a GOYES or RTNYES must follow
+    test: bool,
+}
+
+impl Instruction {
+    fn nibs(nibs: Vec<u8>) -> Self {
+        Instruction { pieces: vec![Piece::Nibs(nibs)], test: false }
+    }
+    fn test(nibs: Vec<u8>) -> Self {
+        Instruction { pieces: vec![Piece::Nibs(nibs)], test: true }
+    }
+}
+
+struct Fixup {
+    line: usize,
+    at: usize,
+    width: usize,
+    origin: usize,
+    target: Target,
+    start: usize,
+}
+
+fn field_code(f: &str) -> Option<u8> {
+    if f == "A" {
+        Some(0xf)
+    } else {
+        FIELDS.iter().position(|x| *x == f).map(|i| i as u8)
+    }
+}
+
+fn position(table: &[&str], m: &str) -> Option<u8> {
+    table.iter().position(|x| *x == m).map(|i| i as u8)
+}
+
+// hex digits, most significant first
+fn hex_digits(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    if s.is_empty() {
+        return None;
+    }
+    s.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect()
+}
+
+// little endian nibbles of a value
+fn value_nibs(value: u32, count: usize) -> Vec<u8> {
+    (0..count).map(|i| ((value >> (4 * i)) & 0xf) as u8).collect()
+}
+
+// register moves of the 10x/11x/12x and 81Af groups: kind, register, R index
+fn register_move(m: &str) -> Option<(u8, u8, u8)> {
+    let b = m.as_bytes();
+    let reg = |c: u8| match c {
+        b'A' => Some(0),
+        b'C' => Some(8),
+        _ => None,
+    };
+    let index = |c: u8| (b'0'..=b'4').contains(&c).then(|| c - b'0');
+    match b {
+        [b'R', i, b'=', r] => Some((0, reg(*r)?, index(*i)?)),
+        [r, b'=', b'R', i] => Some((1, reg(*r)?, index(*i)?)),
+        [r, b'R', i, b'E', b'X'] => Some((2, reg(*r)?, index(*i)?)),
+        _ => None,
+    }
+}
+
+fn target(s: &str) -> Result<Target, String> {
+    if let Some(rel) = s.strip_prefix('*') {
+        let rel = rel.strip_prefix('+').unwrap_or(rel);
+        if rel.is_empty() {
+            return Ok(Target::Relative(0));
+        }
+        return rel.parse().map(Target::Relative).map_err(|_| format!("bad offset {}", s));
+    }
+    Ok(Target::Label(s.to_string()))
+}
+
+fn decimal(s: &str, min: u32, max: u32) -> Result<u8, String> {
+    match s.parse::<u32>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n as u8),
+        _ => Err(format!("{} is not in {}..{}", s, min, max)),
+    }
+}
+
+struct Assembler<'a> {
+    extable: &'a Extable,
+    nibs: Vec<u8>,
+    labels: HashMap<String, usize>,
+    fixups: Vec<Fixup>,
+    line: usize,
+}
+
+impl Assembler<'_> {
+    fn error(&self, message: String) -> Error {
+        Error::AssemblyError(format!("line {}: {}", self.line, message))
+    }
+
+    fn address(&self, s: &str) -> Result<u32, String> {
+        if let Some(digits) = s.strip_prefix('#') {
+            return u32::from_str_radix(digits, 16).map_err(|_| format!("bad address {}", s));
+        }
+        let name = s.strip_prefix('=').unwrap_or(s);
+        self.extable.name_to_addr.get(name).copied().ok_or_else(|| format!("unknown entry {}", name))
+    }
+
+    fn instruction(&self, stmt: &str) -> Result<Instruction, String> {
+        if let Some((_, nibs)) = SIMPLE.iter().find(|(m, _)| *m == stmt) {
+            return Ok(Instruction::nibs(nibs.to_vec()));
+        }
+        if let Some((_, nibs)) = SIMPLE_TESTS.iter().find(|(m, _)| *m == stmt) {
+            return Ok(Instruction::test(nibs.to_vec()));
+        }
+        if let Some(x) = position(&D_REGS, stmt) {
+            return Ok(Instruction::nibs(vec![1, 3, x]));
+        }
+        if let Some(x) = position(&SHIFTS, stmt) {
+            return Ok(Instruction::nibs(vec![8, 1, x]));
+        }
+        if let Some(x) = REGS.iter().position(|r| format!("{}SRB", r) == stmt) {
+            return Ok(Instruction::nibs(vec![8, 1, 0xc + x as u8]));
+        }
+        if let Some((kind, reg, i)) = register_move(stmt) {
+            return Ok(Instruction::nibs(vec![1, kind, reg + i]));
+        }
+        let data = stmt.strip_prefix('$').or_else(|| stmt.strip_prefix("NIBHEX "));
+        if let Some(data) = data {
+            return hex_digits(data).map(Instruction::nibs).ok_or_else(|| format!("bad data {}", data));
+        }
+        let (head, arg) = stmt.split_once(' ').unwrap_or((stmt, ""));
+        if let Some((_, op, width, origin)) = JUMPS.iter().find(|(m, ..)| *m == head) {
+            let offset = Piece::Offset { width: *width, origin: *origin, target: target(arg)? };
+            return Ok(Instruction { pieces: vec![Piece::Nibs(op.to_vec()), offset], test: false });
+        }
+        if head == "GOVLNG" || head == "GOSBVL" {
+            let mut nibs = vec![8, if head == "GOVLNG" { 0xd } else { 0xf }];
+            nibs.extend(value_nibs(self.address(arg)?, 5));
+            return Ok(Instruction::nibs(nibs));
+        }
+        if let Some(nibs) = self.load(head, arg) {
+            return nibs.map(Instruction::nibs);
+        }
+        if let Some(nibs) = self.pointer(head, arg) {
+            return nibs.map(Instruction::nibs);
+        }
+        // P=n in MASD, P= n in HP syntax
+        if let Some(n) = head.strip_prefix("P=").filter(|n| n.parse::<u8>().is_ok()).or((head == "P=").then_some(arg)) {
+            return Ok(Instruction::nibs(vec![2, decimal(n, 0, 15)?]));
+        }
+        if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_digit()) {
+            if let Some((_, op, test)) = COUNTS.iter().find(|(m, ..)| *m == head) {
+                let mut nibs = op.to_vec();
+                nibs.push(decimal(arg, 0, 15)?);
+                return Ok(Instruction { pieces: vec![Piece::Nibs(nibs)], test: *test });
+            }
+            if let Some(a) = position(&DATA, head) {
+                return Ok(Instruction::nibs(vec![1, 5, a + 8, decimal(arg, 1, 16)? - 1]));
+            }
+        }
+        // the field follows a space in HP syntax, a dot in MASD
+        let field = match field_code(arg) {
+            Some(f) => Some((head, f)),
+            None if arg.is_empty() => head.rsplit_once('.').and_then(|(m, f)| Some((m, field_code(f)?))),
+            None => None,
+        };
+        match field.and_then(|(m, f)| self.field_instruction(m, f)) {
+            Some(instruction) => Ok(instruction),
+            None => self.constant(head, arg).ok_or_else(|| format!("unknown instruction {}", stmt)),
+        }
+    }
+
+    // LC/LA: LC 2345 in MASD, LCHEX 2345 in HP syntax
+    fn load(&self, head: &str, arg: &str) -> Option<Result<Vec<u8>, String>> {
+        let mut nibs = match head {
+            "LC" | "LCHEX" => vec![3],
+            "LA" | "LAHEX" => vec![8, 0, 8, 2],
+            _ => return None,
+        };
+        let digits = match hex_digits(arg) {
+            Some(d) if d.len() <= 16 => d,
+            _ => return Some(Err(format!("bad constant {}", arg))),
+        };
+        nibs.push(digits.len() as u8 - 1);
+        nibs.extend(digits.iter().rev());
+        Some(Ok(nibs))
+    }
+
+    // D0=12345 and D0+5 in MASD, D0=(5) #12345 and D0=D0+ 5 in HP syntax
+    fn pointer(&self, head: &str, arg: &str) -> Option<Result<Vec<u8>, String>> {
+        let d = match head.get(..2)? {
+            "D0" => 0,
+            "D1" => 1,
+            _ => return None,
+        };
+        let rest = &head[2..];
+        let add = match rest {
+            "+" | "-" => Some((rest, arg)),
+            _ => rest
+                .strip_prefix(&format!("=D{}", d))
+                .filter(|s| *s == "+" || *s == "-")
+                .map(|s| (s, arg))
+                .or_else(|| rest.get(..1).filter(|s| *s == "+" || *s == "-").map(|s| (s, &rest[1..]))),
+        };
+        if let Some((sign, n)) = add {
+            let op = match (d, sign) {
+                (0, "+") => 6,
+                (1, "+") => 7,
+                (0, _) => 8,
+                _ => 0xc,
+            };
+            return Some(decimal(n, 1, 16).map(|n| vec![1, op, n - 1]));
+        }
+        let rest = rest.strip_prefix('=')?;
+        let (count, value) = match rest.strip_prefix('(') {
+            Some(n) => (n.strip_suffix(')').and_then(|n| n.parse::<usize>().ok()), arg),
+            None => (None, rest),
+        };
+        let digits = match hex_digits(value) {
+            Some(digits) if !value.starts_with('=') && count.is_none_or(|n| n >= digits.len()) => {
+                let mut padded = vec![0; count.unwrap_or(digits.len()) - digits.len()];
+                padded.extend(digits);
+                padded
+            }
+            _ => match self.address(value) {
+                Ok(addr) => value_nibs(addr, 5).into_iter().rev().collect(),
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        let op = match (d, digits.len()) {
+            (0, 2) => 9,
+            (0, 4) => 0xa,
+            (0, 5) => 0xb,
+            (1, 2) => 0xd,
+            (1, 4) => 0xe,
+            (1, 5) => 0xf,
+            _ => return Some(Err(format!("bad pointer {}", value))),
+        };
+        let mut nibs = vec![1, op];
+        nibs.extend(digits.iter().rev());
+        Some(Ok(nibs))
+    }
+
+    fn field_instruction(&self, m: &str, f: u8) -> Option<Instruction> {
+        // field A has short forms, other fields are indexed in FIELDS
+        let a = f == 0xf;
+        let nibs = if let Some(x) = position(&AA, m) {
+            if a { vec![0xc, x] } else { vec![0xa, f, x] }
+        } else if let Some(x) = position(&AB, m) {
+            if a { vec![0xd, x] } else { vec![0xa, f + 8, x] }
+        } else if let Some(x) = position(&BA, m) {
+            if a { vec![0xe, x] } else { vec![0xb, f, x] }
+        } else if let Some(x) = position(&BB, m) {
+            if a { vec![0xf, x] } else { vec![0xb, f + 8, x] }
+        } else if let Some(x) = position(&LOGIC, m) {
+            vec![0, 0xe, f, x]
+        } else if let Some(x) = position(&TEST_EQ, m) {
+            return Some(Instruction::test(if a { vec![8, 0xa, x] } else { vec![9, f, x] }));
+        } else if let Some(x) = position(&TEST_CMP, m) {
+            return Some(Instruction::test(if a { vec![8, 0xb, x] } else { vec![9, f + 8, x] }));
+        } else if let Some(x) = position(&DATA, m) {
+            match f {
+                0xf => vec![1, 4, x],
+                6 => vec![1, 4, x + 8],
+                _ => vec![1, 5, x, f],
+            }
+        } else if let Some(x) = REGS.iter().position(|r| format!("{}SRB", r) == m) {
+            vec![8, 1, 9, f, x as u8]
+        } else if let Some((kind, reg, i)) = m.strip_suffix(".F").and_then(register_move) {
+            vec![8, 1, 0xa, f, kind, reg + i]
+        } else {
+            return None;
+        };
+        Some(Instruction::nibs(nibs))
+    }
+
+    // A=A+16.A in MASD, A=A+CON A,16 in HP syntax
+    fn constant(&self, head: &str, arg: &str) -> Option<Instruction> {
+        let (expr, f, n) = match head.strip_suffix("CON") {
+            Some(expr) => {
+                let (f, n) = arg.split_once(',')?;
+                (expr, f, n)
+            }
+            None => {
+                let (expr, f) = head.rsplit_once('.')?;
+                let split = expr.find(|c: char| c.is_ascii_digit())?;
+                (&expr[..split], f, &expr[split..])
+            }
+        };
+        let (reg, minus) = match expr.as_bytes() {
+            [r, b'=', s, sign @ (b'+' | b'-')] if r == s => (REGS.iter().position(|x| x.as_bytes() == [*r])?, *sign == b'-'),
+            _ => return None,
+        };
+        let x = reg as u8 + if minus { 8 } else { 0 };
+        Some(Instruction::nibs(vec![8, 1, 8, field_code(f)?, x, decimal(n, 1, 16).ok()? - 1]))
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        let start = self.nibs.len();
+        for piece in instruction.pieces {
+            match piece {
+                Piece::Nibs(nibs) => self.nibs.extend(nibs),
+                Piece::Offset { width, origin, target } => {
+                    let at = self.nibs.len();
+                    self.fixups.push(Fixup { line: self.line, at, width, origin: at + origin, target, start });
+                    self.nibs.extend(std::iter::repeat_n(0, width));
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &str, after_test: bool) -> Result<bool, Error> {
+        let (head, arg) = stmt.split_once(' ').unwrap_or((stmt, ""));
+        match head {
+            "GOYES" | "RTNYES" if !after_test => Err(self.error(format!("{} without a test", head))),
+            "GOYES" => {
+                let target = target(arg).map_err(|e| self.error(e))?;
+                self.emit(Instruction { pieces: vec![Piece::Offset { width: 2, origin: 0, target }], test: false });
+                Ok(false)
+            }
+            "RTNYES" => {
+                self.emit(Instruction::nibs(vec![0, 0]));
+                Ok(false)
+            }
+            _ if after_test => Err(self.error(format!("expected GOYES or RTNYES, found {}", stmt))),
+            _ => {
+                let instruction = self.instruction(stmt).map_err(|e| self.error(e))?;
+                let test = instruction.test;
+                self.emit(instruction);
+                Ok(test)
+            }
+        }
+    }
+
+    fn define(&mut self, label: &str) -> Result<(), Error> {
+        if self.labels.insert(label.to_string(), self.nibs.len()).is_some() {
+            return Err(self.error(format!("duplicate label {}", label)));
+        }
+        Ok(())
+    }
+
+    fn assemble(&mut self, source: &str) -> Result<(), Error> {
+        let mut after_test = false;
+        for (i, text) in source.lines().enumerate() {
+            self.line = i + 1;
+            let text = text.split('%').next().unwrap_or_default();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            if let Some(label) = words[0].strip_prefix('*').filter(|l| !l.is_empty() && !l.starts_with(['+', '-'])) {
+                self.define(label)?;
+                continue;
+            }
+            // a name alone in the first column is a label in HP syntax
+            if words.len() == 1 && !text.starts_with(char::is_whitespace) && self.instruction(words[0]).is_err() && !after_test {
+                self.define(words[0])?;
+                continue;
+            }
+            // MASD writes the GOYES on the line of its test
+            let split = words.iter().skip(1).position(|w| *w == "GOYES" || *w == "RTNYES").map_or(words.len(), |p| p + 1);
+            let (test, yes) = words.split_at(split);
+            after_test = self.statement(&test.join(" "), after_test)?;
+            if !yes.is_empty() {
+                after_test = self.statement(&yes.join(" "), after_test)?;
+            }
+        }
+        if after_test {
+            return Err(self.error("test without GOYES or RTNYES".to_string()));
+        }
+        for fixup in &self.fixups {
+            let target = match &fixup.target {
+                Target::Label(l) => match self.labels.get(l) {
+                    Some(t) => *t as isize,
+                    None => return Err(Error::AssemblyError(format!("line {}: unknown label {}", fixup.line, l))),
+                },
+                Target::Relative(r) => fixup.start as isize + r,
+            };
+            let offset = target - fixup.origin as isize;
+            let half = 1isize << (4 * fixup.width - 1);
+            if offset < -half || offset >= half {
+                return Err(Error::AssemblyError(format!("line {}: jump out of range", fixup.line)));
+            }
+            let nibs = value_nibs(offset as u32, fixup.width);
+            self.nibs[fixup.at..fixup.at + fixup.width].copy_from_slice(&nibs);
+        }
+        Ok(())
+    }
+}
+
+/// Nibbles of the assembled source
+pub fn assemble_nibbles(source: &str, extable: &Extable) -> Result<Vec<u8>, Error> {
+    let mut assembler = Assembler { extable, nibs: Vec::new(), labels: HashMap::new(), fixups: Vec::new(), line: 0 };
+    assembler.assemble(source)?;
+    Ok(assembler.nibs)
+}
+
+/// Code object of the assembled source
+pub fn assemble(source: &str, extable: &Extable) -> Result<Obj, Error> {
+    assemble_nibbles(source, extable).map(|nibs| Obj::Code(Blob(nibs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompile::Dialect;
+    use crate::disasm::disassemble;
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        extable.name_to_addr.insert("SAVPTR".to_string(), 0x0679b);
+        extable.addr_to_name.insert(0x0679b, "SAVPTR".to_string());
+        extable
+    }
+
+    fn hex(nibs: &[u8]) -> String {
+        nibs.iter().map(|n| format!("{:X}", n)).collect()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+*LOOP
+  GOSBVL =SAVPTR    % save the RPL pointers
+  A=0.A
+  ?A#0.A GOYES LOOP
+  GOTO LOOP
+  LC 2345
+  D0=12345
+  D1+5
+  P=5
+  A=A+16.A
+  ST=0 10
+  $1A";
+        let nibs = assemble_nibbles(source, &extable()).unwrap();
+        assert_eq!(hex(&nibs), "8FB9760D08AC4F61FF3354321B5432117425818F0F84A1A");
+        assert!(matches!(assemble("RTN", &extable()), Ok(Obj::Code(_))));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let nibs: Vec<u8> = "D08ACBF68FF8FB97603354321B54321164818F0F15A40EF4A60AF28A8008096FF0"
+            .chars()
+            .map(|c| c.to_digit(16).unwrap() as u8)
+            .collect();
+        for dialect in [Dialect::Jazz, Dialect::Masd] {
+            let source = disassemble(&nibs, &extable(), dialect);
+            assert_eq!(hex(&assemble_nibbles(&source, &extable()).unwrap()), hex(&nibs), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble_nibbles(source, &extable()).unwrap_err().to_string();
+        assert_eq!(error("  RTN\n  FOO A"), "Assembly Error: line 2: unknown instruction FOO A");
+        assert_eq!(error("  GOTO NOWHERE"), "Assembly Error: line 1: unknown label NOWHERE");
+        assert_eq!(error("  GOSBVL =NOPE"), "Assembly Error: line 1: unknown entry NOPE");
+        assert_eq!(error("  ?A=0 A\n  RTN"), "Assembly Error: line 2: expected GOYES or RTNYES, found RTN");
+        assert_eq!(error("*L\n*L"), "Assembly Error: line 2: duplicate label L");
+    }
+}
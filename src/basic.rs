@@ -1,6 +1,6 @@
 use winnow::PResult;
 
-use crate::{decompile::Decompiled, nibbles::*, Extable};
+use crate::{decompile::Decompiled, next_integer, nibbles::*, Extable};
 
 // Data structures
 #[derive(Debug)]
@@ -11,24 +11,306 @@ pub struct Real {
 }
 impl Decompiled for Real {
     fn decompile(&self, _extable: &Extable) -> String {
-        format!(
-            "{}", self.as_float()
-        )
+        self.as_decimal().to_string()
+    }
+}
+
+/// Exact decimal number: (-1)^negative * significand * 10^exponent
+/// the significand has no trailing zeros, so that equal numbers compare equal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Decimal {
+    pub negative: bool,
+    pub significand: u64,
+    pub exponent: i32,
+}
+impl Decimal {
+    pub fn new(negative: bool, significand: u64, exponent: i32) -> Self {
+        let mut d = Decimal { negative, significand, exponent };
+        if d.significand == 0 {
+            return Decimal::default();
+        }
+        while d.significand.is_multiple_of(10) {
+            d.significand /= 10;
+            d.exponent += 1;
+        }
+        d
+    }
+    pub fn as_float(&self) -> f64 {
+        // let the float parser do the correct rounding
+        let f: f64 = format!("{}e{}", self.significand, self.exponent).parse().unwrap_or(f64::INFINITY);
+        if self.negative {
+            -f
+        } else {
+            f
+        }
+    }
+    /// Closest decimal with 12 significant digits, which is the precision of the calculator
+    pub fn from_float(f: f64) -> Self {
+        if !f.is_finite() || f == 0.0 {
+            return Decimal::default();
+        }
+        // e.g. 1.23456789012e5
+        let s = format!("{:.11e}", f.abs());
+        let (mantissa, exponent) = s.split_once('e').unwrap();
+        let significand = mantissa.replace('.', "").parse().unwrap();
+        let exponent: i32 = exponent.parse().unwrap();
+        Decimal::new(f < 0.0, significand, exponent - 11)
+    }
+    fn num_digits(&self) -> i32 {
+        self.significand.checked_ilog10().unwrap_or(0) as i32 + 1
+    }
+}
+/// Format like the calculator in STD mode: 12 significant digits at most,
+/// a trailing dot for integers, and scientific notation when the number does not fit
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.significand == 0 {
+            return write!(f, "0.");
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let digits = self.significand.to_string();
+        let n = digits.len() as i32;
+        // exponent of the first digit
+        let e = self.exponent + n - 1;
+        // extended reals have up to 15 digits
+        let width = n.max(12);
+        if (0..width).contains(&e) {
+            if n <= e + 1 {
+                write!(f, "{}{}.", digits, "0".repeat((e + 1 - n) as usize))
+            } else {
+                let (int, frac) = digits.split_at(e as usize + 1);
+                write!(f, "{}.{}", int, frac)
+            }
+        } else if e < 0 && n - e - 1 <= width {
+            write!(f, ".{}{}", "0".repeat((-e - 1) as usize), digits)
+        } else {
+            let (first, rest) = digits.split_at(1);
+            write!(f, "{}.{}E{}", first, rest, e)
+        }
+    }
+}
+
+/// Parse the calculator notation: `-12.5`, `.5`, `1.E12`, `1.23E-12`
+impl std::str::FromStr for Decimal {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::ParseError(format!("invalid number {:?}", s));
+        let (mantissa, exponent) = match s.split_once(['E', 'e']) {
+            Some((m, e)) => (m, e.parse::<i32>().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = format!("{}{}", int, frac);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        // digits past the precision of a u64 are dropped
+        let digits = digits.trim_start_matches('0');
+        let kept = digits.len().min(18);
+        let significand: u64 = digits[..kept].parse().unwrap_or(0);
+        // the exponent is written by the user, so the arithmetic is checked, keeping room
+        // for the digits that are added to it when the number is normalized
+        let frac_len = i32::try_from(frac.len()).ok();
+        let dropped = i32::try_from(digits.len() - kept).ok();
+        let exponent = frac_len
+            .zip(dropped)
+            .and_then(|(frac_len, dropped)| exponent.checked_sub(frac_len)?.checked_add(dropped))
+            .filter(|e| e.checked_add(kept as i32).is_some())
+            .ok_or_else(invalid)?;
+        Ok(Decimal::new(negative, significand, exponent))
     }
 }
+
 impl Real {
-    /// fixme: this is probably not the correct way to convert the hp48 real to a float
+    /// Decode the bcd representation: 3 digits of exponent in ten's complement,
+    /// 12 digits of mantissa with the decimal point after the first one, and a sign nibble
+    pub fn as_decimal(&self) -> Decimal {
+        let mantissa = bcd_value(self.mantissa, 12);
+        let exponent = tens_complement(bcd_value(self.exponent as u64, 3), 3);
+        Decimal::new(self.sign >= 5, mantissa, exponent - 11)
+    }
     pub fn as_float(&self) -> f64 {
-        let mut f = self.mantissa as f64;
-        f /= (1 << 12) as f64;
-        f *= 2f64.powi(self.exponent as i32);
-        if self.sign == 1 {
-            f = -f;
+        self.as_decimal().as_float()
+    }
+    /// Encode a decimal, rounded to 12 significant digits.
+    /// Numbers out of the calculator range are saturated to MAXR or 0
+    pub fn from_decimal(d: Decimal) -> Real {
+        let mut d = d;
+        while d.num_digits() > 12 {
+            let round_up = d.significand % 10 >= 5;
+            d = Decimal::new(d.negative, d.significand / 10 + round_up as u64, d.exponent + 1);
+        }
+        if d.significand == 0 {
+            return Real { exponent: 0, mantissa: 0, sign: 0 };
+        }
+        let mut exponent = d.exponent + d.num_digits() - 1;
+        let mut significand = d.significand * 10u64.pow((12 - d.num_digits()) as u32);
+        if exponent > 499 {
+            exponent = 499;
+            significand = 999_999_999_999;
+        } else if exponent < -499 {
+            return Real { exponent: 0, mantissa: 0, sign: 0 };
+        }
+        let mut mantissa = 0u64;
+        for i in 0..12 {
+            mantissa |= (significand % 10) << (4 * i);
+            significand /= 10;
+        }
+        let mut exponent = exponent.rem_euclid(1000) as u16;
+        let mut bcd_exponent = 0u16;
+        for i in 0..3 {
+            bcd_exponent |= (exponent % 10) << (4 * i);
+            exponent /= 10;
         }
-        f
+        Real { exponent: bcd_exponent, mantissa, sign: if d.negative { 9 } else { 0 } }
+    }
+    pub fn from_float(f: f64) -> Real {
+        Real::from_decimal(Decimal::from_float(f))
+    }
+}
+/// Hex string, shown to the user as a binary integer
+/// nibbles are in memory order, least significant first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexString(pub Vec<u8>);
+
+/// Base used to display binary integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    Bin,
+    Oct,
+    Dec,
+    Hex,
+}
+impl HexString {
+    /// value of the 16 first nibbles
+    pub fn value(&self) -> u64 {
+        self.0.iter().take(16).rev().fold(0, |acc, n| (acc << 4) | (*n & 0xf) as u64)
+    }
+    /// User RPL binary integer, e.g. `# 1F3Ah`, truncated to `word_size` bits (1 to 64)
+    pub fn to_user_binary(&self, base: Base, word_size: u32) -> String {
+        let word_size = word_size.clamp(1, 64);
+        let value = if word_size == 64 { self.value() } else { self.value() & ((1 << word_size) - 1) };
+        match base {
+            Base::Bin => format!("# {:b}b", value),
+            Base::Oct => format!("# {:o}o", value),
+            Base::Dec => format!("# {}d", value),
+            Base::Hex => format!("# {:X}h", value),
+        }
+    }
+    /// System RPL hex string, e.g. `HXS 5 A1F00`, digits in memory order
+    pub fn to_hxs(&self) -> String {
+        let digits: String = self.0.iter().map(|n| format!("{:X}", n)).collect();
+        format!("HXS {:X} {}", self.0.len(), digits)
+    }
+}
+impl Decompiled for HexString {
+    fn decompile(&self, _extable: &Extable) -> String {
+        // user binary integers are at most 64 bits, longer ones are data tables
+        if self.0.len() <= 16 {
+            self.to_user_binary(Base::Hex, 64)
+        } else {
+            self.to_hxs()
+        }
+    }
+}
+
+// value of `digits` bcd digits, packed in an integer (most significant digit in the high nibble)
+fn bcd_value(bcd: u64, digits: usize) -> u64 {
+    (0..digits).rev().fold(0, |acc, i| acc * 10 + ((bcd >> (4 * i)) & 0xf))
+}
+// exponents are stored in ten's complement
+fn tens_complement(value: u64, digits: u32) -> i32 {
+    let modulus = 10i64.pow(digits);
+    let value = value as i64;
+    (if value >= modulus / 2 { value - modulus } else { value }) as i32
+}
+
+/// Extended real: 5 digits of exponent and 15 digits of mantissa
+#[derive(Debug)]
+pub struct ExtReal {
+    pub exponent: u32,
+    pub mantissa: u64,
+    pub sign: u8,
+}
+impl ExtReal {
+    pub fn as_decimal(&self) -> Decimal {
+        let mantissa = bcd_value(self.mantissa, 15);
+        let exponent = tens_complement(bcd_value(self.exponent as u64, 5), 5);
+        Decimal::new(self.sign >= 5, mantissa, exponent - 14)
+    }
+}
+impl Decompiled for ExtReal {
+    fn decompile(&self, _extable: &Extable) -> String {
+        format!("%% {}", self.as_decimal())
+    }
+}
+#[derive(Debug)]
+pub struct ExtComplex {
+    pub real: ExtReal,
+    pub imag: ExtReal,
+}
+impl Decompiled for ExtComplex {
+    fn decompile(&self, _extable: &Extable) -> String {
+        format!("C%% {} {}", self.real.as_decimal(), self.imag.as_decimal())
     }
 }
+
+/// HP49 precision real: mantissa * 10^exponent, both as precision integers
+#[derive(Debug)]
+pub struct LongReal {
+    pub mantissa: ZInt,
+    pub exponent: ZInt,
+}
+impl std::fmt::Display for LongReal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}E{}", self.mantissa, self.exponent)
+    }
+}
+impl Decompiled for LongReal {
+    fn decompile(&self, _extable: &Extable) -> String {
+        format!("L% {}", self)
+    }
+}
+#[derive(Debug)]
+pub struct LongComplex {
+    pub real: LongReal,
+    pub imag: LongReal,
+}
+impl Decompiled for LongComplex {
+    fn decompile(&self, _extable: &Extable) -> String {
+        format!("LC% {} {}", self.real, self.imag)
+    }
+}
+
 // Parsers for various object types
+// precision reals are made of two precision integers without their prolog
+pub(crate) fn next_long_real(input: &mut Nibbles) -> PResult<LongReal> {
+    let mantissa = next_integer(input)?;
+    let exponent = next_integer(input)?;
+    Ok(LongReal { mantissa, exponent })
+}
+pub(crate) fn next_long_complex(input: &mut Nibbles) -> PResult<LongComplex> {
+    let real = next_long_real(input)?;
+    let imag = next_long_real(input)?;
+    Ok(LongComplex { real, imag })
+}
+pub(crate) fn next_ext_real(input: &mut Nibbles) -> PResult<ExtReal> {
+    let exponent = integer5(input)?;
+    let mantissa = integer15(input)?;
+    let sign = integer1(input)?;
+    Ok(ExtReal { exponent, mantissa, sign })
+}
+pub(crate) fn next_ext_complex(input: &mut Nibbles) -> PResult<ExtComplex> {
+    let real = next_ext_real(input)?;
+    let imag = next_ext_real(input)?;
+    Ok(ExtComplex { real, imag })
+}
 pub(crate) fn next_real(input: &mut Nibbles) -> PResult<Real> {
     let exp = integer3(input)?;
     let mantissa = integer12(input)?;
@@ -42,15 +324,75 @@ pub(crate) fn next_real(input: &mut Nibbles) -> PResult<Real> {
     )
 }
 
+/// HP49 precision integer (ZINT), with an arbitrary number of digits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZInt {
+    pub negative: bool,
+    /// decimal digits, most significant first
+    pub digits: Vec<u8>,
+}
+impl ZInt {
+    /// value as a machine integer, if it fits
+    pub fn to_i128(&self) -> Option<i128> {
+        let mut value: i128 = 0;
+        for digit in &self.digits {
+            value = value.checked_mul(10)?.checked_add(*digit as i128)?;
+        }
+        Some(if self.negative { -value } else { value })
+    }
+}
+impl From<i128> for ZInt {
+    fn from(v: i128) -> Self {
+        let digits = v.unsigned_abs().to_string().bytes().map(|b| b - b'0').collect();
+        ZInt { negative: v < 0, digits }
+    }
+}
+impl std::str::FromStr for ZInt {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(crate::Error::ParseError(format!("invalid integer {:?}", s)));
+        }
+        let digits: Vec<u8> = digits.bytes().map(|b| b - b'0').collect();
+        let negative = negative && digits.iter().any(|d| *d != 0);
+        Ok(ZInt { negative, digits })
+    }
+}
+/// digits as shown by the calculator, without leading zeros
+impl std::fmt::Display for ZInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let first = self.digits.iter().position(|d| *d != 0);
+        let Some(first) = first else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        for digit in &self.digits[first..] {
+            write!(f, "{}", digit)?;
+        }
+        Ok(())
+    }
+}
+impl Decompiled for ZInt {
+    fn decompile(&self, _extable: &Extable) -> String {
+        self.to_string()
+    }
+}
+
 #[derive(Debug)]
 pub struct Complex {
     pub real: Real,
     pub imag: Real,
 }
 impl Decompiled for Complex {
-    fn decompile(&self, _extable: &Extable) -> String {
+    fn decompile(&self, extable: &Extable) -> String {
         format!(
-            "{} + {}i", self.real.as_float(), self.imag.as_float()
+            "({},{})", self.real.decompile(extable), self.imag.decompile(extable)
         )
     }
 }
@@ -59,4 +401,117 @@ pub(crate) fn next_complex(input: &mut Nibbles) -> PResult<Complex> {
     let real = next_real(input)?;
     let imag = next_real(input)?;
     Ok(Complex { real, imag })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn real(exponent: u16, mantissa: u64, sign: u8) -> Real {
+        Real { exponent, mantissa, sign }
+    }
+
+    #[test]
+    fn test_real_as_decimal() {
+        assert_eq!(real(0, 0x100000000000, 0).as_decimal(), Decimal::new(false, 1, 0));
+        assert_eq!(real(0x001, 0x125000000000, 9).as_decimal(), Decimal::new(true, 125, -1));
+        assert_eq!(real(0x999, 0x500000000000, 0).as_decimal(), Decimal::new(false, 5, -1));
+        assert_eq!(real(0x499, 0x999999999999, 0).as_decimal(), Decimal::new(false, 999999999999, 488));
+        assert_eq!(real(0, 0, 0).as_decimal(), Decimal::default());
+    }
+
+    #[test]
+    fn test_real_as_float() {
+        assert_eq!(real(0x001, 0x125000000000, 9).as_float(), -12.5);
+        assert_eq!(real(0x998, 0x314159265359, 0).as_float(), 0.0314159265359);
+    }
+
+    #[test]
+    fn test_real_from_decimal() {
+        for r in [real(0, 0x100000000000, 0), real(0x001, 0x125000000000, 9), real(0x999, 0x500000000000, 0),
+                  real(0x499, 0x999999999999, 0), real(0x501, 0x123456789012, 9)] {
+            let back = Real::from_decimal(r.as_decimal());
+            assert_eq!((back.exponent, back.mantissa, back.sign), (r.exponent, r.mantissa, r.sign));
+        }
+        // rounded to 12 digits
+        let r = Real::from_decimal(Decimal::new(false, 1234567890125, 0));
+        assert_eq!((r.exponent, r.mantissa), (0x012, 0x123456789013));
+        let r = Real::from_float(0.1);
+        assert_eq!((r.exponent, r.mantissa, r.sign), (0x999, 0x100000000000, 0));
+        let r = Real::from_float(-2.0 / 3.0);
+        assert_eq!((r.exponent, r.mantissa, r.sign), (0x999, 0x666666666667, 9));
+    }
+
+    #[test]
+    fn test_ext_real() {
+        let r = ExtReal { exponent: 0x99999, mantissa: 0x123456789012345, sign: 9 };
+        assert_eq!(r.as_decimal(), Decimal::new(true, 123456789012345, -15));
+        assert_eq!(r.as_decimal().to_string(), "-.123456789012345");
+        let r = ExtReal { exponent: 0x00002, mantissa: 0x150000000000000, sign: 0 };
+        assert_eq!(r.decompile(&Extable::default()), "%% 150.");
+    }
+
+    #[test]
+    fn test_hex_string() {
+        let h = HexString(vec![0xA, 0x3, 0xF, 0x1, 0x0]);
+        assert_eq!(h.value(), 0x1F3A);
+        assert_eq!(h.to_user_binary(Base::Hex, 64), "# 1F3Ah");
+        assert_eq!(h.to_user_binary(Base::Dec, 64), "# 7994d");
+        assert_eq!(h.to_user_binary(Base::Oct, 64), "# 17472o");
+        assert_eq!(h.to_user_binary(Base::Bin, 8), "# 111010b");
+        assert_eq!(h.to_user_binary(Base::Hex, 12), "# F3Ah");
+        assert_eq!(h.to_hxs(), "HXS 5 A3F10");
+        assert_eq!(h.decompile(&Extable::default()), "# 1F3Ah");
+        let table = HexString((0..20).map(|i| i % 16).collect());
+        assert_eq!(table.decompile(&Extable::default()), "HXS 14 0123456789ABCDEF0123");
+    }
+
+    #[test]
+    fn test_zint() {
+        let big: ZInt = "-123456789012345678901234567890123456789012345678901234567890".parse().unwrap();
+        assert!(big.negative);
+        assert_eq!(big.digits.len(), 60);
+        assert_eq!(big.to_i128(), None);
+        assert_eq!(big.to_string(), "-123456789012345678901234567890123456789012345678901234567890");
+        assert_eq!(ZInt::from(-42).to_i128(), Some(-42));
+        assert_eq!(ZInt::from(0).to_string(), "0");
+        let padded = ZInt { negative: false, digits: vec![0, 0, 1, 2] };
+        assert_eq!(padded.to_string(), "12");
+        assert_eq!(padded.to_i128(), Some(12));
+        assert!("12a".parse::<ZInt>().is_err());
+        assert!("-".parse::<ZInt>().is_err());
+    }
+
+    #[test]
+    fn test_decimal_display() {
+        assert_eq!(Decimal::new(false, 1, 0).to_string(), "1.");
+        assert_eq!(Decimal::new(false, 100, 0).to_string(), "100.");
+        assert_eq!(Decimal::new(true, 125, -1).to_string(), "-12.5");
+        assert_eq!(Decimal::new(false, 5, -1).to_string(), ".5");
+        assert_eq!(Decimal::new(false, 5, -5).to_string(), ".00005");
+        assert_eq!(Decimal::new(false, 123, -14).to_string(), "1.23E-12");
+        assert_eq!(Decimal::new(false, 1, 12).to_string(), "1.E12");
+        assert_eq!(Decimal::new(false, 999999999999, 0).to_string(), "999999999999.");
+        assert_eq!(Decimal::default().to_string(), "0.");
+    }
+
+    #[test]
+    fn test_decimal_from_str() {
+        for s in ["1.", "100.", "-12.5", ".5", ".00005", "1.23E-12", "1.E12", "999999999999.", "0."] {
+            assert_eq!(s.parse::<Decimal>().unwrap().to_string(), s);
+        }
+        assert_eq!("2.5e3".parse::<Decimal>().unwrap(), Decimal::new(false, 25, 2));
+        assert_eq!("-0".parse::<Decimal>().unwrap(), Decimal::default());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("E5".parse::<Decimal>().is_err());
+        assert!(".".parse::<Decimal>().is_err());
+        // exponents out of the i32 range
+        assert!("1.5E-2147483648".parse::<Decimal>().is_err());
+        assert!("10E2147483647".parse::<Decimal>().is_err());
+        assert!("1E99999999999".parse::<Decimal>().is_err());
+        let huge: Decimal = "12E2147483600".parse().unwrap();
+        assert_eq!(Real::from_decimal(huge).as_decimal(), Decimal::new(false, 999_999_999_999, 488));
+        let tiny: Decimal = "1.5E-2147483600".parse().unwrap();
+        assert_eq!(Real::from_decimal(tiny).as_decimal(), Decimal::default());
+    }
+}
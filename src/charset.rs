@@ -0,0 +1,106 @@
+//! Character set of the HP 48/49 calculators
+//!
+//! Characters 0-126 are ASCII, and 160-255 are ISO 8859-1.
+//! 127-159 are calculator specific glyphs (math symbols, arrows, greek letters).
+
+// unicode for characters 127-159
+const HIGH_CHARS: [&str; 33] = [
+    "\u{2592}",  // 127 ▒
+    "\u{2221}",  // 128 ∡
+    "x\u{0305}", // 129 x̄, there is no precomposed form
+    "\u{25BD}",  // 130 ▽
+    "\u{221A}",  // 131 √
+    "\u{222B}",  // 132 ∫
+    "\u{03A3}",  // 133 Σ
+    "\u{25B6}",  // 134 ▶
+    "\u{03C0}",  // 135 π
+    "\u{2202}",  // 136 ∂
+    "\u{2264}",  // 137 ≤
+    "\u{2265}",  // 138 ≥
+    "\u{2260}",  // 139 ≠
+    "\u{03B1}",  // 140 α
+    "\u{2192}",  // 141 →
+    "\u{2190}",  // 142 ←
+    "\u{2193}",  // 143 ↓
+    "\u{2191}",  // 144 ↑
+    "\u{03B3}",  // 145 γ
+    "\u{03B4}",  // 146 δ
+    "\u{03B5}",  // 147 ε
+    "\u{03B7}",  // 148 η
+    "\u{03B8}",  // 149 θ
+    "\u{03BB}",  // 150 λ
+    "\u{03C1}",  // 151 ρ
+    "\u{03C3}",  // 152 σ
+    "\u{03C4}",  // 153 τ
+    "\u{03C9}",  // 154 ω
+    "\u{0394}",  // 155 Δ
+    "\u{03A0}",  // 156 Π
+    "\u{03A9}",  // 157 Ω
+    "\u{25A0}",  // 158 ■
+    "\u{221E}",  // 159 ∞
+];
+
+/// Decode calculator bytes into a String
+pub fn decode(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for &b in bytes {
+        match b {
+            127..=159 => s.push_str(HIGH_CHARS[b as usize - 127]),
+            _ => s.push(b as char),
+        }
+    }
+    s
+}
+
+/// Encode a String into calculator bytes.
+/// characters that do not exist on the calculator are replaced by '?'
+pub fn encode(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == 'x' && chars.peek() == Some(&'\u{0305}') {
+            chars.next();
+            bytes.push(129);
+            continue;
+        }
+        bytes.push(unicode_to_char(c).unwrap_or(b'?'));
+    }
+    bytes
+}
+
+/// Calculator character for a unicode char, if there is one
+pub fn unicode_to_char(c: char) -> Option<u8> {
+    let code = c as u32;
+    if code < 127 || (160..256).contains(&code) {
+        return Some(code as u8);
+    }
+    let mut buf = [0u8; 4];
+    let s: &str = c.encode_utf8(&mut buf);
+    HIGH_CHARS.iter().position(|h| *h == s).map(|i| (i + 127) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_chars_roundtrip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let s = decode(&bytes);
+        assert_eq!(encode(&s), bytes);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"\x8d A"), "\u{2192} A");
+        assert_eq!(decode(&[0xab, 0x87, 0xbb]), "«π»");
+        assert_eq!(decode(&[0x85, 0x83, 0x84, 0x81]), "Σ√∫x\u{0305}");
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("→ A"), b"\x8d A");
+        assert_eq!(encode("x\u{0305}x"), vec![0x81, b'x']);
+        assert_eq!(encode("€"), b"?");
+    }
+}
@@ -0,0 +1,162 @@
+//! User visible names of the built-in commands
+//!
+//! The extable names the User RPL commands with an `x` prefix and ASCII
+//! spellings: `xDUP` is `DUP`, `x>LIST` is `→LIST` and `xSIGMA+` is `Σ+`.
+use crate::Extable;
+
+// names that the generic rules below do not cover
+const RENAMES: &[(&str, &str)] = &[
+    ("x<<", "«"),
+    ("x>>", "»"),
+    ("x>>ABND", "»"),
+    ("x<=?", "≤"),
+    ("x>=?", "≥"),
+    ("x#?", "≠"),
+    ("x>", ">"),
+    ("x<", "<"),
+    ("xSTO>", "▶"),
+    ("xSQRT", "√"),
+    ("xINTEGRAL", "∫"),
+    ("xDER", "∂"),
+    ("xSUM", "Σ"),
+    ("xFACT", "!"),
+    ("xPI", "π"),
+    ("xCONSTANTe", "e"),
+    ("xSUMX", "ΣX"),
+    ("xSUMY", "ΣY"),
+    ("xSUMXY", "ΣXY"),
+    ("xSUMX2", "ΣX²"),
+    ("xSUMY2", "ΣY²"),
+    ("xRPN->", "→"),
+    ("x'", "'"),
+    ("xSILENT'", "'"),
+    // FOR is a START that binds a local variable
+    ("xSTARTVAR", "FOR"),
+    // the END of each structure has its own command, CASE has none and uses the END of IF
+    ("xIFEND", "END"),
+    ("xENDDO", "END"),
+    ("xWHILEEND", "END"),
+    ("xTHENCASE", "THEN"),
+    ("xERRTHEN", "THEN"),
+];
+
+/// User visible name of a built-in command, None if the name is not a User RPL command
+pub fn user_name(name: &str) -> Option<String> {
+    if let Some((_, user)) = RENAMES.iter().find(|(n, _)| *n == name) {
+        return Some(user.to_string());
+    }
+    let name = name.strip_prefix('x').filter(|n| !n.is_empty())?;
+    Some(name.replace("SIGMA", "Σ").replace("->", "→").replace('>', "→"))
+}
+
+/// Extable name of a User RPL command, e.g. `→LIST` is `x>LIST`.
+/// None if several entries have that name, like `END` and `THEN` whose entry
+/// depends on the structure they belong to
+pub fn extable_name<'a>(extable: &'a Extable, user: &str) -> Option<&'a str> {
+    let mut matches = extable
+        .name_to_addr
+        .keys()
+        .filter(|n| n.starts_with('x'))
+        .filter(|n| user_name(n).as_deref() == Some(user));
+    match (matches.next(), matches.next()) {
+        (Some(name), None) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+/// String literal with its escapes, as accepted by the calculator parser
+pub fn quote_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' | '\t' => out.push(c),
+            c if (c as u32) < 32 => out.push_str(&format!("\\{:03}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Content of a string literal written by `quote_string`, None if it is not terminated
+pub fn unquote_string(literal: &str) -> Option<String> {
+    let inner = literal.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            d if d.is_ascii_digit() => {
+                let digits: String = std::iter::once(d).chain(chars.by_ref().take(2)).collect();
+                let code = digits.parse::<u8>().ok()?;
+                out.push_str(&crate::charset::decode(&[code]));
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_name() {
+        assert_eq!(user_name("xDUP").as_deref(), Some("DUP"));
+        assert_eq!(user_name("x+").as_deref(), Some("+"));
+        assert_eq!(user_name("x>LIST").as_deref(), Some("→LIST"));
+        assert_eq!(user_name("xOBJ>").as_deref(), Some("OBJ→"));
+        assert_eq!(user_name("xSTRUCT->").as_deref(), Some("STRUCT→"));
+        assert_eq!(user_name("xSIGMA+").as_deref(), Some("Σ+"));
+        assert_eq!(user_name("x>=?").as_deref(), Some("≥"));
+        assert_eq!(user_name("x<<").as_deref(), Some("«"));
+        assert_eq!(user_name("DUP"), None);
+        assert_eq!(user_name("x"), None);
+    }
+
+    #[test]
+    fn test_extable_name() {
+        let mut extable = Extable::default();
+        extable.name_to_addr.insert("x>LIST".to_string(), 0x39785);
+        extable.name_to_addr.insert("DUP".to_string(), 0x1fb87);
+        assert_eq!(extable_name(&extable, "→LIST"), Some("x>LIST"));
+        assert_eq!(extable_name(&extable, "DUP"), None);
+        // two entries are named STRUCT→, the user name alone cannot pick one
+        extable.name_to_addr.insert("xSTRUCT->".to_string(), 0x38d72);
+        extable.name_to_addr.insert("xSTRUCT>".to_string(), 0x3b6c1);
+        assert_eq!(extable_name(&extable, "STRUCT→"), None);
+    }
+
+    #[test]
+    fn test_structure_names() {
+        let extable = crate::extable::tests::fixture();
+        assert_eq!(extable_name(&extable, "FOR"), Some("xSTARTVAR"));
+        assert_eq!(extable_name(&extable, "START"), Some("xSTART"));
+        assert_eq!(extable_name(&extable, "CASE"), Some("xCASE"));
+        // the END of CASE is the one of IF
+        assert_eq!(user_name("xIFEND").as_deref(), Some("END"));
+        assert_eq!(extable_name(&extable, "END"), None);
+        assert_eq!(extable_name(&extable, "THEN"), None);
+    }
+
+    #[test]
+    fn test_quote_string() {
+        assert_eq!(quote_string("a \"b\" \\"), "\"a \\\"b\\\" \\\\\"");
+        assert_eq!(quote_string("\u{1}\n"), "\"\\001\n\"");
+    }
+
+    #[test]
+    fn test_unquote_string() {
+        for s in ["a \"b\" \\", "\u{1}\n", "", "→Σ"] {
+            assert_eq!(unquote_string(&quote_string(s)).as_deref(), Some(s));
+        }
+        assert_eq!(unquote_string("\"\\141\"").as_deref(), Some("→"));
+        assert_eq!(unquote_string("\"abc"), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! SysRPL compiler
+//!
+//! Compiles the source written by the SysRPL decompiler, in Jazz or MASD syntax,
+//! back into objects. Entries are named through the extable, or written `PTR addr`.
+//! `( … )` are comments, as are lines starting with `*`. MASD `!` directives are
+//! skipped and `@` ends the source.
+use crate::asm::assemble_nibbles;
+use crate::commands::unquote_string;
+use crate::{Blob, Complex, Decimal, Error, Extable, HexString, Obj, Real, StringBlob, ZInt};
+
+/// A word of source text, string literals are single words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Word<'a> {
+    pub text: &'a str,
+    /// byte offset in the source
+    pub start: usize,
+    pub line: usize,
+}
+
+impl Word<'_> {
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+}
+
+/// Split source text into whitespace separated words
+pub(crate) fn words(source: &str) -> Result<Vec<Word<'_>>, Error> {
+    let mut words = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        let first_line = line;
+        let mut end = source.len();
+        if c == '"' {
+            let mut escaped = false;
+            let mut closed = false;
+            for (i, c) in chars.by_ref() {
+                if c == '\n' {
+                    line += 1;
+                }
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = i + 1;
+                        closed = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            if !closed {
+                return Err(Error::CompileError(format!("line {}: unterminated string", first_line)));
+            }
+        } else {
+            while let Some((i, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    end = *i;
+                    break;
+                }
+                chars.next();
+            }
+        }
+        words.push(Word { text: &source[start..end], start, line: first_line });
+    }
+    Ok(words)
+}
+
+struct Compiler<'a> {
+    source: &'a str,
+    words: Vec<Word<'a>>,
+    pos: usize,
+    extable: &'a Extable,
+}
+
+impl<'a> Compiler<'a> {
+    fn error(word: &Word, message: String) -> Error {
+        Error::CompileError(format!("line {}: {}", word.line, message))
+    }
+
+    fn at_line_start(&self, word: &Word) -> bool {
+        word.start == 0 || self.source.as_bytes()[word.start - 1] == b'\n'
+    }
+
+    // next word that is not a comment
+    fn next(&mut self) -> Option<Word<'a>> {
+        loop {
+            let word = *self.words.get(self.pos)?;
+            self.pos += 1;
+            if word.text == "(" {
+                while self.words.get(self.pos).is_some_and(|w| w.text != ")") {
+                    self.pos += 1;
+                }
+                self.pos += 1;
+            } else if word.text == "@" {
+                self.pos = self.words.len();
+            } else if self.at_line_start(&word) && (word.text.starts_with('*') || word.text.starts_with('!')) {
+                while self.words.get(self.pos).is_some_and(|w| w.line == word.line) {
+                    self.pos += 1;
+                }
+            } else {
+                return Some(word);
+            }
+        }
+    }
+
+    fn operand(&mut self, word: &Word) -> Result<Word<'a>, Error> {
+        self.next().ok_or_else(|| Self::error(word, format!("{} without its operand", word.text)))
+    }
+
+    fn hex(&mut self, word: &Word) -> Result<u32, Error> {
+        let operand = self.operand(word)?;
+        u32::from_str_radix(operand.text, 16)
+            .map_err(|_| Self::error(&operand, format!("{} is not hexadecimal", operand.text)))
+    }
+
+    fn real(&mut self, word: &Word) -> Result<Real, Error> {
+        let operand = self.operand(word)?;
+        let d: Decimal = operand.text.parse().map_err(|_| Self::error(&operand, format!("{} is not a real", operand.text)))?;
+        Ok(Real::from_decimal(d))
+    }
+
+    fn composite(&mut self, open: &Word, close: &str) -> Result<Vec<Obj>, Error> {
+        let mut objs = Vec::new();
+        loop {
+            let word = self.next().ok_or_else(|| Self::error(open, format!("{} without {}", open.text, close)))?;
+            if word.text == close {
+                return Ok(objs);
+            }
+            objs.push(self.object(word)?);
+        }
+    }
+
+    fn object(&mut self, word: Word<'a>) -> Result<Obj, Error> {
+        let obj = match word.text {
+            "::" => Obj::Prg(self.composite(&word, ";")?),
+            "{" => Obj::List(self.composite(&word, "}")?),
+            "SYMBOL" => Obj::Symb(self.composite(&word, ";")?),
+            "UNIT" => Obj::Unit(self.composite(&word, ";")?),
+            "MATRIX" => Obj::Matrix(self.composite(&word, ";")?),
+            "ID" => Obj::GlobalName(self.operand(&word)?.text.to_string()),
+            "LAM" => Obj::LocalName(self.operand(&word)?.text.to_string()),
+            "#" => Obj::Bint(self.hex(&word)?),
+            "PTR" => Obj::Ext(self.hex(&word)?),
+            "%" => Obj::Real(self.real(&word)?),
+            "C%" => Obj::Complex(Complex { real: self.real(&word)?, imag: self.real(&word)? }),
+            "ZINT" => {
+                let operand = self.operand(&word)?;
+                Obj::Int(operand.text.parse::<ZInt>().map_err(|e| Self::error(&operand, e.to_string()))?)
+            }
+            "$" => {
+                let operand = self.operand(&word)?;
+                let s = unquote_string(operand.text)
+                    .ok_or_else(|| Self::error(&operand, format!("{} is not a string", operand.text)))?;
+                Obj::CStr(StringBlob(s))
+            }
+            "HXS" => {
+                let len = self.hex(&word)? as usize;
+                let operand = self.operand(&word)?;
+                let digits: Option<Vec<u8>> = operand.text.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect();
+                match digits {
+                    Some(d) if d.len() == len => Obj::HexString(HexString(d)),
+                    _ => return Err(Self::error(&operand, format!("HXS expects {} hexadecimal digits", len))),
+                }
+            }
+            "ROMPTR" | "FPTR" | "FLASHPTR" => {
+                let (first, second) = (self.hex(&word)?, self.hex(&word)?);
+                if first > 0xffff || second > 0xffff {
+                    return Err(Self::error(&word, format!("{} out of range", word.text)));
+                }
+                if word.text == "ROMPTR" {
+                    Obj::RomPtr(first as u16, second as u16)
+                } else {
+                    Obj::FlashPtr(first as u16, second as u16)
+                }
+            }
+            "TAG" => {
+                let tag = self.operand(&word)?.text.to_string();
+                let obj = self.operand(&word)?;
+                Obj::Tagged(tag, Box::new(self.object(obj)?))
+            }
+            "CODE" => {
+                let end = self.words[self.pos..]
+                    .iter()
+                    .position(|w| w.text == "ENDCODE")
+                    .ok_or_else(|| Self::error(&word, "CODE without ENDCODE".to_string()))?;
+                let endcode = self.words[self.pos + end];
+                let source = &self.source[word.end()..endcode.start];
+                self.pos += end + 1;
+                let nibs = assemble_nibbles(source, self.extable)
+                    .map_err(|e| Self::error(&word, format!("in CODE: {}", e)))?;
+                Obj::Code(Blob(nibs))
+            }
+            name => match self.extable.name_to_addr.get(name) {
+                Some(addr) => Obj::Ext(*addr),
+                None => return Err(Self::error(&word, format!("unknown entry {}", name))),
+            },
+        };
+        Ok(obj)
+    }
+}
+
+/// Compile SysRPL source, in Jazz or MASD syntax, into the object it defines
+pub fn compile_sysrpl(source: &str, extable: &Extable) -> Result<Obj, Error> {
+    let mut compiler = Compiler { source, words: words(source)?, pos: 0, extable };
+    let first = compiler.next().ok_or_else(|| Error::CompileError("empty source".to_string()))?;
+    let obj = compiler.object(first)?;
+    match compiler.next() {
+        Some(extra) => Err(Compiler::error(&extra, format!("unexpected {} after the object", extra.text))),
+        None => Ok(obj),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompile::{Decompiled, Dialect};
+    use crate::sysrpl::sysrpl;
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        for (name, addr) in [("x+", 0x3d0f7), ("DUP", 0x03188), ("SAVPTR", 0x0679b)] {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        extable
+    }
+
+    #[test]
+    fn test_words() {
+        let words: Vec<&str> = words("::\n  $ \"a b\\\" ;\"\n;").unwrap().iter().map(|w| w.text).collect();
+        assert_eq!(words, vec!["::", "$", "\"a b\\\" ;\"", ";"]);
+        assert!(super::words("$ \"abc").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let source = "::
+  ID X
+  % 1.5
+  x+
+  PTR 4E2CF
+  {
+    LAM n
+    ZINT -12
+  }
+  TAG T
+    $ \"a
+b\"
+  # 1F
+  C% 1. -2.5
+  HXS 5 A3F10
+  ROMPTR 2F0 01A
+  FPTR 2 6E
+  SYMBOL
+    ID X
+  ;
+  CODE
+L00000
+  GOSBVL =SAVPTR
+  GOTO L00000
+  ENDCODE
+;";
+        let obj = compile_sysrpl(source, &extable()).unwrap();
+        assert_eq!(sysrpl(&obj, &extable(), Dialect::Jazz), source);
+        let masd = obj.decompile_sysrpl(&extable(), Dialect::Masd);
+        let again = compile_sysrpl(&masd, &extable()).unwrap();
+        assert_eq!(sysrpl(&again, &extable(), Dialect::Jazz), source);
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "* a comment\n:: ( DUP twice ) DUP DUP ;\n@ ignored";
+        let obj = compile_sysrpl(source, &extable()).unwrap();
+        assert_eq!(sysrpl(&obj, &extable(), Dialect::Jazz), "::\n  DUP\n  DUP\n;");
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| compile_sysrpl(source, &extable()).unwrap_err().to_string();
+        assert_eq!(error(":: DUP\n  NOPE ;"), "Compile Error: line 2: unknown entry NOPE");
+        assert_eq!(error(":: DUP"), "Compile Error: line 1: :: without ;");
+        assert_eq!(error("% x"), "Compile Error: line 1: x is not a real");
+        assert_eq!(error("DUP DUP"), "Compile Error: line 1: unexpected DUP after the object");
+        assert_eq!(error("CODE\n  FOO\nENDCODE"), "Compile Error: line 1: in CODE: Assembly Error: line 2: unknown instruction FOO");
+    }
+}
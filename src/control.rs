@@ -0,0 +1,384 @@
+//! Control structures of User RPL programs
+//!
+//! The structures are flat commands in the program: `IF` … `THEN` … `ELSE` … `END`,
+//! `IFERR`, `CASE`, `FOR`/`START` … `NEXT`/`STEP`, `WHILE` … `REPEAT` … `END`
+//! and `DO` … `UNTIL` … `END`. They are rebuilt into blocks so that the decompiler
+//! can indent them. Malformed structures are reported as diagnostics and
+//! their commands are left as plain tokens.
+use std::fmt;
+
+use crate::decompile::{decompile_tokens, is_user_program};
+use crate::{Extable, Obj};
+
+/// Decompiled token of a program
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub text: String,
+    /// built-in command, which can be a structure keyword
+    pub command: bool,
+}
+
+/// Problem found while rebuilding the control structures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// index of the token in its program, x<< excluded
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "token {}: {}", self.position, self.message)
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Token(usize),
+    Block { clauses: Vec<Clause>, end: Option<usize> },
+}
+
+#[derive(Debug)]
+struct Clause {
+    /// keyword and FOR variable, none for the test of a CASE clause
+    header: Vec<usize>,
+    body: Vec<Node>,
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Parser<'_> {
+    fn keyword(&self) -> Option<&str> {
+        self.tokens.get(self.pos).filter(|t| t.command).map(|t| t.text.as_str())
+    }
+
+    fn diagnostic(&mut self, position: usize, message: String) {
+        self.diagnostics.push(Diagnostic { position, message });
+    }
+
+    // tokens and blocks up to one of the stop keywords, which is not consumed
+    fn sequence(&mut self, stops: &[&str]) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        while self.pos < self.tokens.len() {
+            let start = self.pos;
+            match self.keyword() {
+                Some(k) if stops.contains(&k) => break,
+                Some("IF" | "IFERR") => nodes.push(self.if_block()),
+                Some("CASE") => nodes.push(self.case_block()),
+                Some("FOR" | "START") => nodes.push(self.loop_block()),
+                Some("WHILE") => nodes.push(self.two_clauses_block("REPEAT")),
+                Some("DO") => nodes.push(self.two_clauses_block("UNTIL")),
+                Some(k @ ("THEN" | "ELSE" | "END" | "NEXT" | "STEP" | "REPEAT" | "UNTIL")) => {
+                    let message = format!("unexpected {}", k);
+                    self.diagnostic(start, message);
+                    self.pos += 1;
+                    nodes.push(Node::Token(start));
+                }
+                _ => {
+                    self.pos += 1;
+                    nodes.push(Node::Token(start));
+                }
+            }
+        }
+        nodes
+    }
+
+    // consume the keyword if it is one of the expected ones
+    fn expect(&mut self, expected: &[&str], opener: usize) -> Option<usize> {
+        match self.keyword() {
+            Some(k) if expected.contains(&k) => {
+                self.pos += 1;
+                Some(self.pos - 1)
+            }
+            _ => {
+                let message = format!("{} without {}", self.tokens[opener].text, expected.join(" or "));
+                self.diagnostic(opener, message);
+                None
+            }
+        }
+    }
+
+    fn if_block(&mut self) -> Node {
+        let opener = self.pos;
+        self.pos += 1;
+        let mut clauses = vec![Clause { header: vec![opener], body: self.sequence(&["THEN"]) }];
+        let mut end = None;
+        if let Some(then) = self.expect(&["THEN"], opener) {
+            clauses.push(Clause { header: vec![then], body: self.sequence(&["ELSE", "END"]) });
+            if self.keyword() == Some("ELSE") {
+                self.pos += 1;
+                clauses.push(Clause { header: vec![self.pos - 1], body: self.sequence(&["END"]) });
+            }
+            end = self.expect(&["END"], opener);
+        }
+        Node::Block { clauses, end }
+    }
+
+    // CASE test THEN body END … default END
+    fn case_block(&mut self) -> Node {
+        let opener = self.pos;
+        self.pos += 1;
+        let mut body = Vec::new();
+        loop {
+            let test = self.sequence(&["THEN", "END"]);
+            if self.keyword() == Some("THEN") {
+                let then = self.pos;
+                self.pos += 1;
+                let clauses = vec![
+                    Clause { header: vec![], body: test },
+                    Clause { header: vec![then], body: self.sequence(&["END"]) },
+                ];
+                let end = self.expect(&["END"], then);
+                body.push(Node::Block { clauses, end });
+                if end.is_none() {
+                    return Node::Block { clauses: vec![Clause { header: vec![opener], body }], end: None };
+                }
+            } else {
+                // default clause
+                body.extend(test);
+                let end = self.expect(&["END"], opener);
+                return Node::Block { clauses: vec![Clause { header: vec![opener], body }], end };
+            }
+        }
+    }
+
+    fn loop_block(&mut self) -> Node {
+        let opener = self.pos;
+        self.pos += 1;
+        let mut header = vec![opener];
+        if self.tokens[opener].text == "FOR" && self.pos < self.tokens.len() {
+            header.push(self.pos);
+            self.pos += 1;
+        }
+        let body = self.sequence(&["NEXT", "STEP"]);
+        let end = self.expect(&["NEXT", "STEP"], opener);
+        Node::Block { clauses: vec![Clause { header, body }], end }
+    }
+
+    // WHILE test REPEAT body END, DO body UNTIL test END
+    fn two_clauses_block(&mut self, middle: &str) -> Node {
+        let opener = self.pos;
+        self.pos += 1;
+        let mut clauses = vec![Clause { header: vec![opener], body: self.sequence(&[middle]) }];
+        let mut end = None;
+        if let Some(m) = self.expect(&[middle], opener) {
+            clauses.push(Clause { header: vec![m], body: self.sequence(&["END"]) });
+            end = self.expect(&["END"], opener);
+        }
+        Node::Block { clauses, end }
+    }
+}
+
+fn parse(tokens: &[Token]) -> (Vec<Node>, Vec<Diagnostic>) {
+    let mut parser = Parser { tokens, pos: 0, diagnostics: Vec::new() };
+    let nodes = parser.sequence(&[]);
+    (nodes, parser.diagnostics)
+}
+
+// line breaks of nested programs are indented, those of strings are kept
+fn indent(text: &str, prefix: &str) -> String {
+    let mut out = String::from(prefix);
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        out.push(c);
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '\n' if !in_string => out.push_str(prefix),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn render(nodes: &[Node], tokens: &[Token], depth: usize, lines: &mut Vec<String>) {
+    let prefix = "  ".repeat(depth);
+    let mut line: Vec<&str> = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Token(i) => line.push(&tokens[*i].text),
+            Node::Block { clauses, end } => {
+                if !line.is_empty() {
+                    lines.push(indent(&line.join(" "), &prefix));
+                    line.clear();
+                }
+                for clause in clauses {
+                    let header: Vec<&str> = clause.header.iter().map(|i| tokens[*i].text.as_str()).collect();
+                    let simple = clause.body.iter().all(|n| matches!(n, Node::Token(_)));
+                    if simple {
+                        let words: Vec<&str> = header
+                            .into_iter()
+                            .chain(clause.body.iter().map(|n| match n {
+                                Node::Token(i) => tokens[*i].text.as_str(),
+                                _ => unreachable!(),
+                            }))
+                            .collect();
+                        if !words.is_empty() {
+                            lines.push(indent(&words.join(" "), &prefix));
+                        }
+                    } else if header.is_empty() {
+                        render(&clause.body, tokens, depth, lines);
+                    } else {
+                        lines.push(indent(&header.join(" "), &prefix));
+                        render(&clause.body, tokens, depth + 1, lines);
+                    }
+                }
+                if let Some(end) = end {
+                    lines.push(indent(&tokens[*end].text, &prefix));
+                }
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(indent(&line.join(" "), &prefix));
+    }
+}
+
+/// `« … »` with its structures indented, on a single line when there are none
+pub(crate) fn decompile_program(tokens: &[Token]) -> String {
+    if tokens.is_empty() {
+        return "« »".to_string();
+    }
+    let (nodes, _) = parse(tokens);
+    let mut lines = Vec::new();
+    render(&nodes, tokens, 1, &mut lines);
+    if lines.len() == 1 && !lines[0].contains('\n') {
+        format!("« {} »", lines[0].trim_start())
+    } else {
+        format!("«\n{}\n»", lines.join("\n"))
+    }
+}
+
+/// Malformed structures of a User RPL program and of the programs it contains.
+/// Positions are relative to the program containing the structure
+pub fn check_structures(obj: &Obj, extable: &Extable) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    match obj {
+        Obj::Prg(v) => {
+            if is_user_program(v, extable) {
+                let (_, d) = parse(&decompile_tokens(&v[1..v.len() - 1], extable));
+                diagnostics.extend(d);
+            }
+            for o in v {
+                diagnostics.extend(check_structures(o, extable));
+            }
+        }
+        Obj::List(v) => {
+            for o in v {
+                diagnostics.extend(check_structures(o, extable));
+            }
+        }
+        _ => {}
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<Token> {
+        let keywords = [
+            "IF", "IFERR", "THEN", "ELSE", "END", "CASE", "FOR", "START", "NEXT", "STEP", "WHILE", "REPEAT", "DO", "UNTIL",
+            "DUP", "+", ">",
+        ];
+        src.split(' ')
+            .map(|t| Token { text: t.to_string(), command: keywords.contains(&t) })
+            .collect()
+    }
+
+    #[test]
+    fn test_if_then_else() {
+        let t = tokens("X 0 > IF THEN \"pos\" ELSE \"neg\" END");
+        assert_eq!(decompile_program(&t), "«\n  X 0 >\n  IF\n  THEN \"pos\"\n  ELSE \"neg\"\n  END\n»");
+        let t = tokens("IF X 0 > THEN 1 END");
+        assert_eq!(decompile_program(&t), "«\n  IF X 0 >\n  THEN 1\n  END\n»");
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let t = tokens("1 10 FOR i 1 i START DUP + NEXT 2 STEP");
+        assert_eq!(
+            decompile_program(&t),
+            "«\n  1 10\n  FOR i\n    1 i\n    START DUP +\n    NEXT\n    2\n  STEP\n»"
+        );
+        let t = tokens("WHILE X REPEAT DO 1 UNTIL 2 END END");
+        assert_eq!(
+            decompile_program(&t),
+            "«\n  WHILE X\n  REPEAT\n    DO 1\n    UNTIL 2\n    END\n  END\n»"
+        );
+    }
+
+    #[test]
+    fn test_case() {
+        let t = tokens("CASE X 1 > THEN \"big\" END X 0 > THEN \"small\" END \"none\" END");
+        assert_eq!(
+            decompile_program(&t),
+            "«\n  CASE\n    X 1 >\n    THEN \"big\"\n    END\n    X 0 >\n    THEN \"small\"\n    END\n    \"none\"\n  END\n»"
+        );
+    }
+
+    // commands as the calculator compiles them, named by the real extable
+    #[test]
+    fn test_compiled_structures() {
+        let extable = crate::extable::tests::fixture();
+        let x = |name: &str| Obj::Ext(extable.name_to_addr[name]);
+        let int = |v: i128| Obj::Int(crate::ZInt::from(v));
+        let program = [
+            int(1),
+            int(3),
+            x("xSTARTVAR"),
+            Obj::LocalName("i".to_string()),
+            x("xCASE"),
+            Obj::LocalName("i".to_string()),
+            int(1),
+            x("x=="),
+            x("xTHENCASE"),
+            int(10),
+            x("xIFEND"),
+            int(20),
+            x("xIFEND"),
+            x("xNEXT"),
+        ];
+        let tokens = decompile_tokens(&program, &extable);
+        assert_eq!(parse(&tokens).1, vec![]);
+        assert_eq!(
+            decompile_program(&tokens),
+            "«\n  1 3\n  FOR i\n    CASE\n      i 1 ==\n      THEN 10\n      END\n      20\n    END\n  NEXT\n»"
+        );
+    }
+
+    #[test]
+    fn test_nested_program_indent() {
+        let t = vec![
+            Token { text: "«\n  IF A\n  THEN \"a\nb\"\n  END\n»".to_string(), command: false },
+            Token { text: "IF".to_string(), command: true },
+            Token { text: "THEN".to_string(), command: true },
+            Token { text: "END".to_string(), command: true },
+        ];
+        assert_eq!(
+            decompile_program(&t),
+            "«\n  «\n    IF A\n    THEN \"a\nb\"\n    END\n  »\n  IF\n  THEN\n  END\n»"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (_, d) = parse(&tokens("IF X THEN 1"));
+        assert_eq!(d, vec![Diagnostic { position: 0, message: "IF without END".to_string() }]);
+        let (_, d) = parse(&tokens("1 END DUP"));
+        assert_eq!(d, vec![Diagnostic { position: 1, message: "unexpected END".to_string() }]);
+        let (_, d) = parse(&tokens("WHILE X END"));
+        assert_eq!(d[0].message, "unexpected END");
+        assert_eq!(d[1].message, "WHILE without REPEAT");
+        // malformed structures are kept as they are
+        assert_eq!(decompile_program(&tokens("IF X THEN 1")), "«\n  IF X\n  THEN 1\n»");
+        assert_eq!(decompile_program(&tokens("1 END")), "« 1 END »");
+    }
+}
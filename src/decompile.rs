@@ -1,29 +1,38 @@
-use crate::{hexdump_nibbles, nibbles::Nibbles, Extable, Obj};
+use crate::commands::{quote_string, user_name};
+use crate::control::{decompile_program, Token};
+use crate::locals::{arrow_names, is_arrow};
+use crate::disasm::disassemble;
+use crate::sysrpl::sysrpl;
+use crate::{charset, consts::prolog_to_id, hexdump_nibbles, nibbles::Nibbles, Expr, Extable, Obj, Unit};
 
 /// module to decompile hp4x objects
 /// this is a little bit like debug, but more adapted to viewing the objects
 /// in a human readble format
-///
-
 pub trait Decompiled {
     fn decompile(&self, extable: &Extable) -> String;
+    /// SysRPL source, for objects that have one
+    fn decompile_sysrpl(&self, extable: &Extable, _dialect: Dialect) -> String {
+        self.decompile(extable)
+    }
+}
+
+/// Syntax of the SysRPL decompiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// Jazz and the HP tools (RPLCOMP)
+    #[default]
+    Jazz,
+    /// MASD, the built-in compiler of the HP49
+    Masd,
 }
 
 impl Decompiled for Obj {
     fn decompile(&self, extable: &Extable) -> String {
         match self {
-            Obj::Array(arr) => {
-                let mut s = format!(
-                    "Array of type {:x}, with {} dimensions: [",
-                    arr.obj_type, arr.num_dims
-                );
-                for dim in &arr.dims {
-                    s.push_str(&format!("{}, ", dim));
-                }
-                s.push_str("]\n");
-                for obj in &arr.objects {
-                    s.push_str(&obj.decompile(extable));
-                }
+            Obj::Array(arr) => decompile_array(&arr.elements(), &arr.dims, extable),
+            Obj::Aplet(blob) | Obj::MiniFont(blob) => {
+                let mut s = format!("{}\n", prolog_to_id(self.prolog()));
+                s.push_str(&hexdump_nibbles(Nibbles::new(&blob.0), Some(usize::MAX)));
                 s
             }
             Obj::ExtObj(typ_code, blob, typ) => {
@@ -34,17 +43,45 @@ impl Decompiled for Obj {
                 s.push_str(&hexdump_nibbles(nib, Some(usize::MAX)));
                 s
             }
-            Obj::CStr(str) => str.0.clone(),
+            Obj::CStr(str) => quote_string(&str.0),
             Obj::Real(v) => v.decompile(extable),
+            Obj::Int(v) => v.decompile(extable),
+            Obj::ExtReal(v) => v.decompile(extable),
+            Obj::ExtComplex(v) => v.decompile(extable),
+            Obj::Bint(v) => format!("BINT {}", v),
+            Obj::Char(c) => {
+                let s = charset::decode(&[*c]);
+                if *c > b' ' {
+                    format!("CHR {}", s)
+                } else {
+                    format!("CHR \\{:02X}", c)
+                }
+            }
+            Obj::RomPtr(lib, cmd) => format!("ROMPTR {:03X} {:03X}", lib, cmd),
+            Obj::FlashPtr(bank, cmd) => format!("FPTR {:X} {:X}", bank, cmd),
             Obj::Complex(v) => v.decompile(extable),
-            Obj::Prg(v) => {
-                let mut s = "// Program:\n".to_string();
-                for obj in v {
-                    s.push_str(&obj.decompile(extable));
-                    s.push(char::from(10));
+            Obj::HexString(v) => v.decompile(extable),
+            Obj::Grob(v) => v.decompile(extable),
+            Obj::LongReal(v) => v.decompile(extable),
+            Obj::LongComplex(v) => v.decompile(extable),
+            Obj::Matrix(v) => {
+                // 2 dimensional matrices are made of row matrices
+                if !v.is_empty() && v.iter().all(|o| matches!(o, Obj::Matrix(_))) {
+                    let rows: Vec<String> = v.iter().map(|o| o.decompile(extable)).collect();
+                    format!("[{}]", rows.join(""))
+                } else {
+                    // elements are objects, so names keep their quotes
+                    let elements: Vec<String> = v.iter().map(|o| o.decompile(extable)).collect();
+                    format!("[ {} ]", elements.join(" "))
                 }
-                s
             }
+            Obj::GlobalName(name) | Obj::LocalName(name) => format!("'{}'", name),
+            Obj::List(v) if v.is_empty() => "{ }".to_string(),
+            Obj::List(v) => format!("{{ {} }}", join_tokens(&decompile_tokens(v, extable))),
+            Obj::Prg(v) if is_user_program(v, extable) => {
+                decompile_program(&decompile_tokens(&v[1..v.len() - 1], extable))
+            }
+            Obj::Prg(_) => sysrpl(self, extable, Dialect::Jazz),
             Obj::Ext(v) => {
                 if let Some(n) = extable.addr_to_name.get(v) {
                     n.clone()
@@ -52,10 +89,95 @@ impl Decompiled for Obj {
                     format!("Ext{:x}", v)
                 }
             }
+            Obj::Unit(tokens) => match Unit::from_tokens(tokens, extable) {
+                Some(unit) => unit.decompile(extable),
+                // without the extable the operators cannot be recognized
+                None => format!("{:?}", self),
+            },
+            Obj::Symb(tokens) => match Expr::from_tokens(tokens, extable) {
+                Some(expr) => format!("'{}'", expr),
+                None => format!("{:?}", self),
+            },
+            Obj::Code(blob) => format!("CODE\n{}\nENDCODE", disassemble(&blob.0, extable, Dialect::Jazz)),
             Obj::Semi() => ";".to_string(),
+            Obj::Tagged(tag, obj) => format!(":{}:{}", tag, obj.decompile(extable)),
             _ => format!("{:?}", self),
         }
     }
+    fn decompile_sysrpl(&self, extable: &Extable, dialect: Dialect) -> String {
+        match dialect {
+            Dialect::Jazz => sysrpl(self, extable, dialect),
+            // MASD needs to be told the source is RPL
+            Dialect::Masd => format!("!NO CODE\n!RPL\n{}\n@", sysrpl(self, extable, dialect)),
+        }
+    }
+}
+
+// User RPL programs are wrapped in x<< and x>>, or x>>ABND for the body of →
+pub(crate) fn is_user_program(tokens: &[Obj], extable: &Extable) -> bool {
+    let name = |o: Option<&Obj>| match o {
+        Some(Obj::Ext(addr)) => extable.addr_to_name.get(addr).map(|n| n.as_str()),
+        _ => None,
+    };
+    tokens.len() >= 2 && name(tokens.first()) == Some("x<<") && matches!(name(tokens.last()), Some("x>>" | "x>>ABND"))
+}
+
+// tokens of programs and lists: names are not quoted, unless preceded by a quote command,
+// and the built-in commands have their user visible names
+pub(crate) fn decompile_tokens(tokens: &[Obj], extable: &Extable) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut iter = tokens.iter();
+    while let Some(obj) = iter.next() {
+        let name = match obj {
+            Obj::Ext(addr) => extable.addr_to_name.get(addr).map(|n| n.as_str()),
+            _ => None,
+        };
+        let text = match (obj, name) {
+            (_, Some("'" | "x'" | "xSILENT'")) if !iter.as_slice().is_empty() => {
+                let quoted = iter.next().unwrap();
+                match quoted {
+                    // names and symbolics already have their quotes
+                    Obj::Symb(_) | Obj::GlobalName(_) | Obj::LocalName(_) => quoted.decompile(extable),
+                    _ => format!("'{}'", quoted.decompile(extable)),
+                }
+            }
+            // the locals bound by → are its header
+            (_, Some(n)) if is_arrow(n) => {
+                let names = arrow_names(iter.as_slice());
+                for _ in 0..names.len() {
+                    iter.next();
+                }
+                std::iter::once("→").chain(names).collect::<Vec<_>>().join(" ")
+            }
+            (Obj::GlobalName(n) | Obj::LocalName(n), _) => n.clone(),
+            (_, Some(n)) => user_name(n).unwrap_or_else(|| n.to_string()),
+            _ => obj.decompile(extable),
+        };
+        out.push(Token { text, command: name.is_some() });
+    }
+    out
+}
+
+fn join_tokens(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.text.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+// arrays are stored in row major order, e.g. [[ 1. 2. ][ 3. 4. ]]
+fn decompile_array(elements: &[Option<&Obj>], dims: &[usize], extable: &Extable) -> String {
+    if dims.len() <= 1 {
+        // missing elements of linked arrays have no representation
+        let items: Vec<String> = elements
+            .iter()
+            .map(|e| e.map(|o| o.decompile(extable)).unwrap_or_else(|| "?".to_string()))
+            .collect();
+        return format!("[ {} ]", items.join(" "));
+    }
+    let chunk = dims[1..].iter().product::<usize>().max(1);
+    let rows: Vec<String> = elements
+        .chunks(chunk)
+        .map(|row| decompile_array(row, &dims[1..], extable))
+        .collect();
+    format!("[{}]", rows.join(""))
 }
 
 #[cfg(test)]
@@ -68,14 +190,14 @@ mod test {
     fn test_decompile_real() {
         let r = Obj::Real(crate::Real {
             exponent: 0,
-            mantissa: 0x1000,
+            mantissa: 0x100000000000,
             sign: 0,
         });
         let extable = Extable {
             name_to_addr: HashMap::new(),
             addr_to_name: HashMap::new(),
         };
-        assert_eq!(r.decompile(&extable), "1");
+        assert_eq!(r.decompile(&extable), "1.");
     }
 
     #[test]
@@ -83,12 +205,12 @@ mod test {
         let r = Obj::Complex(crate::Complex {
             real: crate::Real {
                 exponent: 0,
-                mantissa: 0x1000,
+                mantissa: 0x100000000000,
                 sign: 0,
             },
             imag: crate::Real {
                 exponent: 0,
-                mantissa: 0x1000,
+                mantissa: 0x100000000000,
                 sign: 0,
             },
         });
@@ -96,7 +218,7 @@ mod test {
             name_to_addr: HashMap::new(),
             addr_to_name: HashMap::new(),
         };
-        assert_eq!(r.decompile(&extable), "1 + 1i");
+        assert_eq!(r.decompile(&extable), "(1.,1.)");
     }
 
     #[test]
@@ -125,11 +247,105 @@ mod test {
         assert_eq!(r.decompile(&extable), ";");
     }
     #[test]
+    fn test_decompile_tagged() {
+        let r = Obj::Tagged(
+            "label".to_string(),
+            Box::new(Obj::Real(crate::Real {
+                exponent: 1,
+                mantissa: 0x420000000000,
+                sign: 0,
+            })),
+        );
+        let extable = Extable::default();
+        assert_eq!(r.decompile(&extable), ":label:42.");
+    }
+    #[test]
+    fn test_decompile_fixed_size() {
+        let extable = Extable::default();
+        assert_eq!(Obj::Bint(12).decompile(&extable), "BINT 12");
+        assert_eq!(Obj::Char(b'A').decompile(&extable), "CHR A");
+        assert_eq!(Obj::Char(0x8d).decompile(&extable), "CHR \u{2192}");
+        assert_eq!(Obj::Char(b'\n').decompile(&extable), "CHR \\0A");
+        assert_eq!(Obj::RomPtr(0x2F0, 0x1A).decompile(&extable), "ROMPTR 2F0 01A");
+        assert_eq!(Obj::FlashPtr(2, 0x6E).decompile(&extable), "FPTR 2 6E");
+        let one = || crate::ExtReal { exponent: 0, mantissa: 0x100000000000000, sign: 0 };
+        assert_eq!(Obj::ExtReal(one()).decompile(&extable), "%% 1.");
+        let c = crate::ExtComplex { real: one(), imag: one() };
+        assert_eq!(Obj::ExtComplex(c).decompile(&extable), "C%% 1. 1.");
+    }
+    #[test]
+    fn test_decompile_matrix() {
+        let extable = Extable::default();
+        let int = |v: i128| Obj::Int(crate::ZInt::from(v));
+        let m = Obj::Matrix(vec![
+            Obj::Matrix(vec![Obj::GlobalName("X".to_string()), int(2)]),
+            Obj::Matrix(vec![int(3), int(4)]),
+        ]);
+        assert_eq!(m.decompile(&extable), "[[ 'X' 2 ][ 3 4 ]]");
+        assert_eq!(Obj::Matrix(vec![int(1), int(2)]).decompile(&extable), "[ 1 2 ]");
+        let r = crate::LongReal { mantissa: crate::ZInt::from(-15), exponent: crate::ZInt::from(3) };
+        assert_eq!(Obj::LongReal(r).decompile(&extable), "L% -15E3");
+    }
+    #[test]
+    fn test_decompile_array() {
+        let extable = Extable::default();
+        let real = |v: f64| Obj::Real(crate::Real::from_float(v));
+        let arr = Obj::Array(crate::Array {
+            obj_type: crate::consts::DOREAL,
+            num_dims: 2,
+            dims: vec![2, 2],
+            objects: vec![real(1.0), real(2.0), real(3.0), real(4.5)],
+            links: None,
+        });
+        assert_eq!(arr.decompile(&extable), "[[ 1. 2. ][ 3. 4.5 ]]");
+        let linked = Obj::Array(crate::Array {
+            obj_type: crate::consts::DOREAL,
+            num_dims: 1,
+            dims: vec![3],
+            objects: vec![real(1.0)],
+            links: Some(vec![Some(0), None, Some(0)]),
+        });
+        assert_eq!(linked.decompile(&extable), "[ 1. ? 1. ]");
+        let cmp = Obj::Array(crate::Array {
+            obj_type: crate::consts::DOCMP,
+            num_dims: 1,
+            dims: vec![1],
+            objects: vec![Obj::Complex(crate::Complex { real: crate::Real::from_float(1.0), imag: crate::Real::from_float(-2.0) })],
+            links: None,
+        });
+        assert_eq!(cmp.decompile(&extable), "[ (1.,-2.) ]");
+    }
+    #[test]
+    fn test_decompile_user_program() {
+        let mut extable = Extable::default();
+        for (addr, name) in [(1, "x<<"), (2, "x>>"), (3, "xDUP"), (4, "x+"), (5, "xSILENT'"), (6, "x>LIST"), (7, "xSTO")] {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        let prg = Obj::Prg(vec![
+            Obj::Ext(1),
+            Obj::Ext(3),
+            Obj::Real(crate::Real::from_float(1.0)),
+            Obj::Ext(4),
+            Obj::Ext(5),
+            Obj::GlobalName("X".to_string()),
+            Obj::Ext(7),
+            Obj::CStr(crate::StringBlob("a\"b".to_string())),
+            Obj::List(vec![Obj::GlobalName("A".to_string()), Obj::Ext(6)]),
+            Obj::Prg(vec![Obj::Ext(1), Obj::LocalName("n".to_string()), Obj::Ext(2)]),
+            Obj::Ext(2),
+        ]);
+        assert_eq!(prg.decompile(&extable), "« DUP 1. + 'X' STO \"a\\\"b\" { A →LIST } « n » »");
+        assert_eq!(Obj::Prg(vec![Obj::Ext(1), Obj::Ext(2)]).decompile(&extable), "« »");
+        assert_eq!(Obj::GlobalName("X".to_string()).decompile(&extable), "'X'");
+        assert_eq!(Obj::List(vec![]).decompile(&extable), "{ }");
+    }
+    #[test]
     fn test_decompile_prg() {
         let r = Obj::Prg(vec![
             Obj::Real(crate::Real {
                 exponent: 0,
-                mantissa: 0x1000,
+                mantissa: 0x100000000000,
                 sign: 0,
             }),
             Obj::Semi(),
@@ -138,6 +354,23 @@ mod test {
             name_to_addr: HashMap::new(),
             addr_to_name: HashMap::new(),
         };
-        assert_eq!(r.decompile(&extable), "// Program:\n1\n;\n");
+        assert_eq!(r.decompile(&extable), "::\n  % 1.\n  ;\n;");
+    }
+
+    #[test]
+    fn test_decompile_nested_prg() {
+        let r = Obj::Prg(vec![
+            Obj::Real(crate::Real {
+                exponent: 0,
+                mantissa: 0x100000000000,
+                sign: 0,
+            }),
+            Obj::Prg(vec![]),
+        ]);
+        let extable = Extable {
+            name_to_addr: HashMap::new(),
+            addr_to_name: HashMap::new(),
+        };
+        assert_eq!(r.decompile(&extable), "::\n  % 1.\n  ::\n  ;\n;");
     }
 }
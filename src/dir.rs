@@ -1,6 +1,7 @@
 use winnow::PResult;
 use crate::{Obj, next_obj};
 use crate::nibbles::*;
+use crate::charset;
 
 #[derive(Debug)]
 pub struct DirEntity {
@@ -9,8 +10,11 @@ pub struct DirEntity {
 }
 #[derive(Debug)]
 pub struct Dir {
+    /// number of the attached library, 0x7FF if none
+    pub attached_libs: u16,
     pub entities: Vec<DirEntity>,
 }
+#[allow(clippy::redundant_field_names)]
 pub(crate) fn next_dir_entity(nibs: &mut Nibbles) -> PResult<DirEntity> {
     // An entity consists of an ASCIX name followed by the contents of
     // the object. We need to read the ASCIX name, then the object
@@ -18,7 +22,7 @@ pub(crate) fn next_dir_entity(nibs: &mut Nibbles) -> PResult<DirEntity> {
     // dump_nibbles(nibs);
     let name = pascal_string(nibs)?;
     let name_len_back = integer2usize(nibs)?;
-    debug_assert_eq!(name.len(), name_len_back);
+    debug_assert_eq!(charset::encode(&name).len(), name_len_back);
     let obj = next_obj(nibs)?;
     Ok(
         DirEntity {
@@ -47,8 +51,11 @@ pub(crate) fn next_dir(nibs: &mut Nibbles) -> PResult<Dir> {
     let attached_libs = integer3(nibs)?;
     let offset = integer5(nibs)?;
     let _zeros = integer5(nibs)?;
+    if offset == 0 {
+        // empty directory
+        return Ok(Dir { attached_libs, entities: Vec::new() });
+    }
     let offset = offset - 10;
-    println!("attached_libs: {:x}, offset: {:x}", attached_libs, offset);
     let mut entities = Vec::new();
     // offset is the offset of the last object of the directory
     let last_obj_slice = &nibs[offset as usize..];
@@ -61,5 +68,5 @@ pub(crate) fn next_dir(nibs: &mut Nibbles) -> PResult<Dir> {
         // 5 nibbles after each object but the last one (so that the dir can be parsed backwards)
         let _ = integer5(nibs)?;
     }
-    Ok(Dir { entities: entities })
+    Ok(Dir { attached_libs, entities })
 }
@@ -0,0 +1,606 @@
+//! Saturn disassembler
+//!
+//! Disassembles the body of code objects, in MASD or HP (SASM, used by Jazz) syntax.
+//! Relative jumps into the code get labels named after their offset, e.g. `L0001A`,
+//! jumps out of it are written relative to the instruction, e.g. `*+35`.
+//! `GOSBVL`/`GOVLNG` targets are named through the extable.
+//! Nibbles that do not decode are written as data.
+use std::collections::BTreeSet;
+
+use crate::decompile::Dialect;
+use crate::Extable;
+
+pub(crate) const FIELDS: [&str; 8] = ["P", "WP", "XS", "X", "S", "M", "B", "W"];
+
+// arithmetic tables, by the last opcode nibble
+pub(crate) const AA: [&str; 16] = [
+    "A=A+B", "B=B+C", "C=C+A", "D=D+C", "A=A+A", "B=B+B", "C=C+C", "D=D+D", "B=B+A", "C=C+B", "A=A+C", "C=C+D", "A=A-1",
+    "B=B-1", "C=C-1", "D=D-1",
+];
+pub(crate) const AB: [&str; 16] = [
+    "A=0", "B=0", "C=0", "D=0", "A=B", "B=C", "C=A", "D=C", "B=A", "C=B", "A=C", "C=D", "ABEX", "BCEX", "ACEX", "CDEX",
+];
+pub(crate) const BA: [&str; 16] = [
+    "A=A-B", "B=B-C", "C=C-A", "D=D-C", "A=A+1", "B=B+1", "C=C+1", "D=D+1", "B=B-A", "C=C-B", "A=A-C", "C=C-D", "A=B-A",
+    "B=C-B", "C=A-C", "D=C-D",
+];
+pub(crate) const BB: [&str; 16] = [
+    "ASL", "BSL", "CSL", "DSL", "ASR", "BSR", "CSR", "DSR", "A=-A", "B=-B", "C=-C", "D=-D", "A=-A-1", "B=-B-1", "C=-C-1",
+    "D=-D-1",
+];
+pub(crate) const LOGIC: [&str; 16] = [
+    "A=A&B", "B=B&C", "C=C&A", "D=D&C", "B=B&A", "C=C&B", "A=A&C", "C=C&D", "A=A!B", "B=B!C", "C=C!A", "D=D!C", "B=B!A",
+    "C=C!B", "A=A!C", "C=C!D",
+];
+pub(crate) const TEST_EQ: [&str; 16] = [
+    "?A=B", "?B=C", "?C=A", "?D=C", "?A#B", "?B#C", "?C#A", "?D#C", "?A=0", "?B=0", "?C=0", "?D=0", "?A#0", "?B#0", "?C#0",
+    "?D#0",
+];
+pub(crate) const TEST_CMP: [&str; 16] = [
+    "?A>B", "?B>C", "?C>A", "?D>C", "?A<B", "?B<C", "?C<A", "?D<C", "?A>=B", "?B>=C", "?C>=A", "?D>=C", "?A<=B", "?B<=C",
+    "?C<=A", "?D<=C",
+];
+pub(crate) const DATA: [&str; 8] = ["DAT0=A", "DAT1=A", "A=DAT0", "A=DAT1", "DAT0=C", "DAT1=C", "C=DAT0", "C=DAT1"];
+pub(crate) const D_REGS: [&str; 16] = [
+    "D0=A", "D1=A", "AD0EX", "AD1EX", "D0=C", "D1=C", "CD0EX", "CD1EX", "D0=AS", "D1=AS", "AD0XS", "AD1XS", "D0=CS", "D1=CS",
+    "CD0XS", "CD1XS",
+];
+pub(crate) const SHIFTS: [&str; 8] = ["ASLC", "BSLC", "CSLC", "DSLC", "ASRC", "BSRC", "CSRC", "DSRC"];
+pub(crate) const REGS: [&str; 4] = ["A", "B", "C", "D"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    /// mnemonic without operand
+    Simple(String),
+    /// mnemonic and field
+    Field(String, &'static str),
+    /// mnemonic and a number of nibbles or a bit number
+    Count(String, u8),
+    /// `A=A+CON f,n`
+    Constant(String, &'static str, u8),
+    /// test, with its GOYES target, None for RTNYES
+    Test(String, Option<&'static str>, Option<isize>),
+    /// relative jump and its target
+    Jump(&'static str, isize),
+    /// GOVLNG/GOSBVL and the absolute address
+    Absolute(&'static str, u32),
+    /// LC or LA with the digits, most significant first
+    Load(char, String),
+    /// D0=(n) with the digits, most significant first
+    Pointer(u8, String),
+    /// D0=D0+ n, D1=D1- n
+    PointerAdd(u8, char, u8),
+    /// P= n
+    SetP(u8),
+    /// undecoded nibbles
+    Data(Vec<u8>),
+}
+
+struct Decoder<'a> {
+    nibs: &'a [u8],
+    start: usize,
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn nib(&mut self) -> Option<u8> {
+        let n = *self.nibs.get(self.pos)?;
+        self.pos += 1;
+        Some(n)
+    }
+    // little endian value
+    fn value(&mut self, count: usize) -> Option<u32> {
+        let mut v = 0u32;
+        for i in 0..count {
+            v |= (self.nib()? as u32) << (4 * i);
+        }
+        Some(v)
+    }
+    fn signed(&mut self, count: usize) -> Option<isize> {
+        let v = self.value(count)? as isize;
+        let half = 1 << (4 * count - 1);
+        Some(if v >= half { v - 2 * half } else { v })
+    }
+    // digits in reading order, displayed most significant first
+    fn digits(&mut self, count: usize) -> Option<String> {
+        let mut s = Vec::new();
+        for _ in 0..count {
+            s.push(format!("{:X}", self.nib()?));
+        }
+        s.reverse();
+        Some(s.concat())
+    }
+    fn goyes(&mut self, test: String, field: Option<&'static str>) -> Option<Op> {
+        let at = self.pos as isize;
+        let offset = self.signed(2)?;
+        Some(Op::Test(test, field, (offset != 0).then_some(at + offset)))
+    }
+    fn field(f: u8) -> &'static str {
+        if f == 0xf {
+            "A"
+        } else {
+            FIELDS[(f & 7) as usize]
+        }
+    }
+
+    fn decode(&mut self) -> Option<Op> {
+        let s = |m: &str| Some(Op::Simple(m.to_string()));
+        let op = match self.nib()? {
+            0x0 => match self.nib()? {
+                0x0 => return s("RTNSXM"),
+                0x1 => return s("RTN"),
+                0x2 => return s("RTNSC"),
+                0x3 => return s("RTNCC"),
+                0x4 => return s("SETHEX"),
+                0x5 => return s("SETDEC"),
+                0x6 => return s("RSTK=C"),
+                0x7 => return s("C=RSTK"),
+                0x8 => return s("CLRST"),
+                0x9 => return s("C=ST"),
+                0xa => return s("ST=C"),
+                0xb => return s("CSTEX"),
+                0xc => return s("P=P+1"),
+                0xd => return s("P=P-1"),
+                0xe => {
+                    let f = self.nib()?;
+                    if f != 0xf && f > 7 {
+                        return None;
+                    }
+                    let x = self.nib()?;
+                    Op::Field(LOGIC[x as usize].to_string(), Self::field(f))
+                }
+                _ => return s("RTI"),
+            },
+            0x1 => match self.nib()? {
+                r @ 0x0..=0x2 => {
+                    let n = self.nib()?;
+                    let (reg, i) = match n {
+                        0..=4 => ('A', n),
+                        8..=0xc => ('C', n - 8),
+                        _ => return None,
+                    };
+                    let m = match r {
+                        0 => format!("R{}={}", i, reg),
+                        1 => format!("{}=R{}", reg, i),
+                        _ => format!("{}R{}EX", reg, i),
+                    };
+                    Op::Simple(m)
+                }
+                0x3 => Op::Simple(D_REGS[self.nib()? as usize].to_string()),
+                0x4 => {
+                    let x = self.nib()?;
+                    Op::Field(DATA[(x & 7) as usize].to_string(), if x < 8 { "A" } else { "B" })
+                }
+                0x5 => {
+                    let a = self.nib()?;
+                    let f = self.nib()?;
+                    let m = DATA[(a & 7) as usize].to_string();
+                    if a < 8 {
+                        if f > 7 {
+                            return None;
+                        }
+                        Op::Field(m, FIELDS[f as usize])
+                    } else {
+                        Op::Count(m, f + 1)
+                    }
+                }
+                0x6 => Op::PointerAdd(0, '+', self.nib()? + 1),
+                0x7 => Op::PointerAdd(1, '+', self.nib()? + 1),
+                0x8 => Op::PointerAdd(0, '-', self.nib()? + 1),
+                0xc => Op::PointerAdd(1, '-', self.nib()? + 1),
+                0x9 => Op::Pointer(0, self.digits(2)?),
+                0xa => Op::Pointer(0, self.digits(4)?),
+                0xb => Op::Pointer(0, self.digits(5)?),
+                0xd => Op::Pointer(1, self.digits(2)?),
+                0xe => Op::Pointer(1, self.digits(4)?),
+                _ => Op::Pointer(1, self.digits(5)?),
+            },
+            0x2 => Op::SetP(self.nib()?),
+            0x3 => {
+                let n = self.nib()? as usize + 1;
+                Op::Load('C', self.digits(n)?)
+            }
+            n @ (0x4 | 0x5) => {
+                let at = self.pos as isize;
+                let offset = self.signed(2)?;
+                match (n, offset) {
+                    (0x4, 0) => Op::Simple("RTNC".to_string()),
+                    (0x5, 0) => Op::Simple("RTNNC".to_string()),
+                    (0x4, 2) => Op::Simple("NOP3".to_string()),
+                    (0x4, _) => Op::Jump("GOC", at + offset),
+                    _ => Op::Jump("GONC", at + offset),
+                }
+            }
+            0x6 => {
+                let at = self.pos as isize;
+                let offset = self.signed(3)?;
+                match offset {
+                    3 => Op::Simple("NOP4".to_string()),
+                    4 if self.nib() == Some(0) => Op::Simple("NOP5".to_string()),
+                    4 => return None,
+                    _ => Op::Jump("GOTO", at + offset),
+                }
+            }
+            0x7 => {
+                let offset = self.signed(3)?;
+                Op::Jump("GOSUB", self.pos as isize + offset)
+            }
+            0x8 => self.decode_8()?,
+            0x9 => {
+                let f = self.nib()?;
+                let x = self.nib()? as usize;
+                let test = if f < 8 { TEST_EQ[x] } else { TEST_CMP[x] };
+                self.goyes(test.to_string(), Some(FIELDS[(f & 7) as usize]))?
+            }
+            0xa => {
+                let f = self.nib()?;
+                let x = self.nib()? as usize;
+                let m = if f < 8 { AA[x] } else { AB[x] };
+                Op::Field(m.to_string(), FIELDS[(f & 7) as usize])
+            }
+            0xb => {
+                let f = self.nib()?;
+                let x = self.nib()? as usize;
+                let m = if f < 8 { BA[x] } else { BB[x] };
+                Op::Field(m.to_string(), FIELDS[(f & 7) as usize])
+            }
+            0xc => Op::Field(AA[self.nib()? as usize].to_string(), "A"),
+            0xd => Op::Field(AB[self.nib()? as usize].to_string(), "A"),
+            0xe => Op::Field(BA[self.nib()? as usize].to_string(), "A"),
+            _ => Op::Field(BB[self.nib()? as usize].to_string(), "A"),
+        };
+        Some(op)
+    }
+
+    fn decode_8(&mut self) -> Option<Op> {
+        let s = |m: &str| Some(Op::Simple(m.to_string()));
+        let op = match self.nib()? {
+            0x0 => match self.nib()? {
+                0x0 => return s("OUT=CS"),
+                0x1 => return s("OUT=C"),
+                0x2 => return s("A=IN"),
+                0x3 => return s("C=IN"),
+                0x4 => return s("UNCNFG"),
+                0x5 => return s("CONFIG"),
+                0x6 => return s("C=ID"),
+                0x7 => return s("SHUTDN"),
+                0x8 => match self.nib()? {
+                    0x0 => return s("INTON"),
+                    0x1 if self.nib()? == 0 => return s("RSI"),
+                    0x1 => return None,
+                    0x2 => {
+                        let n = self.nib()? as usize + 1;
+                        Op::Load('A', self.digits(n)?)
+                    }
+                    0x3 => return s("BUSCB"),
+                    0x4 => Op::Count("ABIT=0".to_string(), self.nib()?),
+                    0x5 => Op::Count("ABIT=1".to_string(), self.nib()?),
+                    0x8 => Op::Count("CBIT=0".to_string(), self.nib()?),
+                    0x9 => Op::Count("CBIT=1".to_string(), self.nib()?),
+                    t @ (0x6 | 0x7 | 0xa | 0xb) => {
+                        let m = ["?ABIT=0", "?ABIT=1", "", "", "?CBIT=0", "?CBIT=1"][t as usize - 6];
+                        let bit = self.nib()?;
+                        self.goyes(format!("{} {}", m, bit), None)?
+                    }
+                    0xc => return s("PC=(A)"),
+                    0xd => return s("BUSCD"),
+                    0xe => return s("PC=(C)"),
+                    _ => return s("INTOFF"),
+                },
+                0x9 => return s("C=C+P+1"),
+                0xa => return s("RESET"),
+                0xb => return s("BUSCC"),
+                0xc => Op::Count("C=P".to_string(), self.nib()?),
+                0xd => Op::Count("P=C".to_string(), self.nib()?),
+                0xe => return s("SREQ?"),
+                _ => Op::Count("CPEX".to_string(), self.nib()?),
+            },
+            0x1 => match self.nib()? {
+                x @ 0x0..=0x7 => Op::Simple(SHIFTS[x as usize].to_string()),
+                0x8 => {
+                    let f = self.nib()?;
+                    let x = self.nib()?;
+                    let n = self.nib()? + 1;
+                    let reg = REGS[(x & 3) as usize];
+                    let sign = match x {
+                        0..=3 => '+',
+                        8..=0xb => '-',
+                        _ => return None,
+                    };
+                    Op::Constant(format!("{}={}{}", reg, reg, sign), Self::field(f), n)
+                }
+                0x9 => {
+                    let f = self.nib()?;
+                    let x = self.nib()?;
+                    if x > 3 {
+                        return None;
+                    }
+                    Op::Field(format!("{}SRB", REGS[x as usize]), Self::field(f))
+                }
+                0xa => {
+                    let f = self.nib()?;
+                    let kind = self.nib()?;
+                    let n = self.nib()?;
+                    let (reg, i) = match n {
+                        0..=4 => ('A', n),
+                        8..=0xc => ('C', n - 8),
+                        _ => return None,
+                    };
+                    let m = match kind {
+                        0 => format!("R{}={}.F", i, reg),
+                        1 => format!("{}=R{}.F", reg, i),
+                        2 => format!("{}R{}EX.F", reg, i),
+                        _ => return None,
+                    };
+                    Op::Field(m, Self::field(f))
+                }
+                0xb => match self.nib()? {
+                    0x2 => return s("PC=A"),
+                    0x3 => return s("PC=C"),
+                    0x4 => return s("A=PC"),
+                    0x5 => return s("C=PC"),
+                    0x6 => return s("APCEX"),
+                    0x7 => return s("CPCEX"),
+                    _ => return None,
+                },
+                x => Op::Simple(format!("{}SRB", REGS[(x - 0xc) as usize])),
+            },
+            0x2 => match self.nib()? {
+                0x1 => return s("XM=0"),
+                0x2 => return s("SB=0"),
+                0x4 => return s("SR=0"),
+                0x8 => return s("MP=0"),
+                0xf => return s("CLRHST"),
+                n => Op::Count("HST=0".to_string(), n),
+            },
+            0x3 => {
+                let n = self.nib()?;
+                let test = match n {
+                    0x1 => "?XM=0".to_string(),
+                    0x2 => "?SB=0".to_string(),
+                    0x4 => "?SR=0".to_string(),
+                    0x8 => "?MP=0".to_string(),
+                    n => format!("?HST=0 {}", n),
+                };
+                self.goyes(test, None)?
+            }
+            0x4 => Op::Count("ST=0".to_string(), self.nib()?),
+            0x5 => Op::Count("ST=1".to_string(), self.nib()?),
+            t @ 0x6..=0x9 => {
+                let m = ["?ST=0", "?ST=1", "?P#", "?P="][t as usize - 6];
+                let n = self.nib()?;
+                self.goyes(format!("{} {}", m, n), None)?
+            }
+            0xa => {
+                let x = self.nib()? as usize;
+                self.goyes(TEST_EQ[x].to_string(), Some("A"))?
+            }
+            0xb => {
+                let x = self.nib()? as usize;
+                self.goyes(TEST_CMP[x].to_string(), Some("A"))?
+            }
+            0xc => {
+                let at = self.pos as isize;
+                Op::Jump("GOLONG", at + self.signed(4)?)
+            }
+            0xd => Op::Absolute("GOVLNG", self.value(5)?),
+            0xe => {
+                let offset = self.signed(4)?;
+                Op::Jump("GOSUBL", self.pos as isize + offset)
+            }
+            _ => Op::Absolute("GOSBVL", self.value(5)?),
+        };
+        Some(op)
+    }
+}
+
+// decoded instructions with their offsets, undecodable nibbles are grouped as data
+fn decode_all(nibs: &[u8]) -> Vec<(usize, Op)> {
+    let mut out: Vec<(usize, Op)> = Vec::new();
+    let mut pos = 0;
+    while pos < nibs.len() {
+        let mut d = Decoder { nibs, start: pos, pos };
+        match d.decode() {
+            Some(op) => {
+                out.push((d.start, op));
+                pos = d.pos;
+            }
+            None => {
+                match out.last_mut() {
+                    Some((_, Op::Data(data))) => data.push(nibs[pos]),
+                    _ => out.push((pos, Op::Data(vec![nibs[pos]]))),
+                }
+                pos += 1;
+            }
+        }
+    }
+    out
+}
+
+fn label(offset: isize) -> String {
+    format!("L{:05X}", offset)
+}
+
+// label if the target starts an instruction, relative to the instruction otherwise
+fn target(target: isize, at: usize, labels: &BTreeSet<isize>) -> String {
+    if labels.contains(&target) {
+        label(target)
+    } else {
+        let rel = target - at as isize;
+        if rel >= 0 {
+            format!("*+{}", rel)
+        } else {
+            format!("*{}", rel)
+        }
+    }
+}
+
+fn format_op(op: &Op, at: usize, labels: &BTreeSet<isize>, extable: &Extable, dialect: Dialect) -> Vec<String> {
+    let masd = dialect == Dialect::Masd;
+    let with_field = |m: &str, f: &str| if masd { format!("{}.{}", m, f) } else { format!("{} {}", m, f) };
+    let line = match op {
+        Op::Simple(m) => m.clone(),
+        Op::Field(m, f) => with_field(m, f),
+        Op::Count(m, n) => format!("{} {}", m, n),
+        Op::Constant(m, f, n) => {
+            if masd {
+                format!("{}{}.{}", m, n, f)
+            } else {
+                format!("{}CON {},{}", m, f, n)
+            }
+        }
+        Op::Test(m, f, goyes) => {
+            let test = match f {
+                Some(f) => with_field(m, f),
+                None => m.clone(),
+            };
+            let yes = match goyes {
+                Some(t) => format!("GOYES {}", target(*t, at, labels)),
+                None => "RTNYES".to_string(),
+            };
+            if masd {
+                format!("{} {}", test, yes)
+            } else {
+                return vec![test, yes];
+            }
+        }
+        Op::Jump(m, t) => format!("{} {}", m, target(*t, at, labels)),
+        Op::Absolute(m, addr) => match extable.addr_to_name.get(addr) {
+            Some(name) if masd => format!("{} {}", m, name),
+            Some(name) => format!("{} ={}", m, name),
+            None => format!("{} #{:05X}", m, addr),
+        },
+        Op::Load(r, digits) => {
+            if masd {
+                format!("L{} {}", r, digits)
+            } else {
+                format!("L{}HEX {}", r, digits)
+            }
+        }
+        Op::Pointer(d, digits) => {
+            if masd {
+                format!("D{}={}", d, digits)
+            } else {
+                format!("D{}=({}) #{}", d, digits.len(), digits)
+            }
+        }
+        Op::PointerAdd(d, sign, n) => {
+            if masd {
+                format!("D{}{}{}", d, sign, n)
+            } else {
+                format!("D{}=D{}{} {}", d, d, sign, n)
+            }
+        }
+        Op::SetP(n) => {
+            if masd {
+                format!("P={}", n)
+            } else {
+                format!("P= {}", n)
+            }
+        }
+        Op::Data(nibs) => {
+            let hex: String = nibs.iter().map(|n| format!("{:X}", n)).collect();
+            if masd {
+                format!("${}", hex)
+            } else {
+                format!("NIBHEX {}", hex)
+            }
+        }
+    };
+    vec![line]
+}
+
+/// Assembly source of code, labels start the lines and instructions are indented
+pub fn disassemble(nibs: &[u8], extable: &Extable, dialect: Dialect) -> String {
+    let ops = decode_all(nibs);
+    let starts: BTreeSet<isize> = ops.iter().map(|(at, _)| *at as isize).collect();
+    let labels: BTreeSet<isize> = ops
+        .iter()
+        .filter_map(|(_, op)| match op {
+            Op::Jump(_, t) | Op::Test(_, _, Some(t)) => Some(*t),
+            _ => None,
+        })
+        .filter(|t| starts.contains(t))
+        .collect();
+    let mut lines = Vec::new();
+    for (at, op) in &ops {
+        if labels.contains(&(*at as isize)) {
+            let l = label(*at as isize);
+            lines.push(if dialect == Dialect::Masd { format!("*{}", l) } else { l });
+        }
+        for line in format_op(op, *at, &labels, extable, dialect) {
+            lines.push(format!("  {}", line));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nibbles(hex: &str) -> Vec<u8> {
+        hex.chars().map(|c| c.to_digit(16).unwrap() as u8).collect()
+    }
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        extable.name_to_addr.insert("SAVPTR".to_string(), 0x0679b);
+        extable.addr_to_name.insert(0x0679b, "SAVPTR".to_string());
+        extable
+    }
+
+    #[test]
+    fn test_instructions() {
+        let cases = [
+            ("01", "RTN", "RTN"),
+            ("8F B9760", "GOSBVL =SAVPTR", "GOSBVL SAVPTR"),
+            ("8D 34120", "GOVLNG #02143", "GOVLNG #02143"),
+            ("33 5432", "LCHEX 2345", "LC 2345"),
+            ("1B 54321", "D0=(5) #12345", "D0=12345"),
+            ("164", "D0=D0+ 5", "D0+5"),
+            ("25", "P= 5", "P=5"),
+            ("C0", "A=A+B A", "A=A+B.A"),
+            ("A60", "A=A+B B", "A=A+B.B"),
+            ("AF2", "C=0 W", "C=0.W"),
+            ("142", "A=DAT0 A", "A=DAT0.A"),
+            ("15A4", "A=DAT0 5", "A=DAT0 5"),
+            ("818F0F", "A=A+CON A,16", "A=A+16.A"),
+            ("84A", "ST=0 10", "ST=0 10"),
+            ("808C", "PC=(A)", "PC=(A)"),
+            ("809", "C=C+P+1", "C=C+P+1"),
+            ("81B4", "A=PC", "A=PC"),
+            ("0EF4", "B=B&A A", "B=B&A.A"),
+            ("420", "NOP3", "NOP3"),
+            ("6300", "NOP4", "NOP4"),
+            ("8A800", "?A=0 A\n  RTNYES", "?A=0.A RTNYES"),
+        ];
+        for (hex, hp, masd) in cases {
+            let nibs = nibbles(&hex.replace(' ', ""));
+            assert_eq!(disassemble(&nibs, &extable(), Dialect::Jazz), format!("  {}", hp), "{}", hex);
+            assert_eq!(disassemble(&nibs, &extable(), Dialect::Masd), format!("  {}", masd), "{}", hex);
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        // A=0 A ; ?A#0 A GOYES L00000 ; GOTO L00000 ; RTN
+        let nibs = nibbles("D08ACBF68FF01");
+        assert_eq!(
+            disassemble(&nibs, &extable(), Dialect::Jazz),
+            "L00000\n  A=0 A\n  ?A#0 A\n  GOYES L00000\n  GOTO L00000\n  RTN"
+        );
+        assert_eq!(
+            disassemble(&nibs, &extable(), Dialect::Masd),
+            "*L00000\n  A=0.A\n  ?A#0.A GOYES L00000\n  GOTO L00000\n  RTN"
+        );
+        // jumps out of the code are relative
+        assert_eq!(disassemble(&nibbles("6FF0"), &extable(), Dialect::Jazz), "  GOTO *+256");
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(disassemble(&nibbles("1A"), &extable(), Dialect::Jazz), "  NIBHEX 1A");
+        assert_eq!(disassemble(&nibbles("011A"), &extable(), Dialect::Masd), "  RTN\n  $1A");
+    }
+}
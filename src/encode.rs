@@ -0,0 +1,496 @@
+//! module to encode hp4x objects back into nibbles
+//! this is the reverse of the parsers: an object parsed from a file
+//! is encoded back to the same nibbles
+use crate::charset;
+use crate::consts::*;
+use crate::nibbles::*;
+use crate::{Array, Dir, ExtReal, Library, LongReal, Obj, Real, Xlib, ZInt};
+
+impl Obj {
+    /// prolog address identifying the type of the object
+    pub fn prolog(&self) -> u32 {
+        match self {
+            Obj::Dir(_) => DORRP,
+            Obj::Real(_) => DOREAL,
+            Obj::Int(_) => DOINT,
+            Obj::CStr(_) => DOCSTR,
+            Obj::Prg(_) => DOCOL,
+            Obj::List(_) => DOLIST,
+            Obj::Symb(_) => DOSYMB,
+            Obj::Unit(_) => DOEXT,
+            Obj::Complex(_) => DOCMP,
+            Obj::LongReal(_) => DOLNGREAL,
+            Obj::LongComplex(_) => DOLNGCMP,
+            Obj::Matrix(_) => DOMATRIX,
+            Obj::Array(arr) if arr.links.is_some() => DOLNKARRY,
+            Obj::Array(_) => DOARRY,
+            Obj::Ext(prolog) => *prolog,
+            Obj::ExtObj(prolog, _, _) => *prolog,
+            Obj::Bint(_) => DOBINT,
+            Obj::Char(_) => DOCHAR,
+            Obj::ExtReal(_) => DOEREAL,
+            Obj::ExtComplex(_) => DOECMP,
+            Obj::RomPtr(_, _) => DOROMP,
+            Obj::FlashPtr(_, _) => DOFLASHP,
+            Obj::Code(_) => DOCODE,
+            Obj::GlobalName(_) => DOIDNT,
+            Obj::LocalName(_) => DOLAM,
+            Obj::Tagged(_, _) => DOTAG,
+            Obj::Semi() => SEMI,
+            Obj::Library(_) => DOLIB,
+            Obj::HexString(_) => DOHSTR,
+            Obj::Grob(_) => DOGROB,
+            Obj::Aplet(_) => DOAPLET,
+            Obj::MiniFont(_) => DOMINIFONT,
+        }
+    }
+}
+
+/// Encode an object, prolog included
+pub fn encode_obj(obj: &Obj, w: &mut NibbleWriter) {
+    w.put_integer5(obj.prolog());
+    encode_body(obj, w);
+}
+
+/// Encode an object into a vector of nibbles
+pub fn to_nibbles(obj: &Obj) -> Vec<u8> {
+    let mut w = NibbleWriter::new();
+    encode_obj(obj, &mut w);
+    w.into_nibbles()
+}
+
+fn encode_real(r: &Real, w: &mut NibbleWriter) {
+    w.put_integer3(r.exponent);
+    w.put_integer12(r.mantissa);
+    w.put_integer1(r.sign);
+}
+
+fn encode_zint(v: &ZInt, w: &mut NibbleWriter) {
+    w.put_lv(|w| {
+        for digit in v.digits.iter().rev() {
+            w.put_nibble(*digit);
+        }
+        w.put_nibble(if v.negative { 9 } else { 0 });
+    });
+}
+
+fn encode_long_real(r: &LongReal, w: &mut NibbleWriter) {
+    encode_zint(&r.mantissa, w);
+    encode_zint(&r.exponent, w);
+}
+
+fn encode_ext_real(r: &ExtReal, w: &mut NibbleWriter) {
+    w.put_integer5(r.exponent);
+    w.put_integer15(r.mantissa);
+    w.put_integer1(r.sign);
+}
+
+// encode the object without its prolog
+fn encode_body(obj: &Obj, w: &mut NibbleWriter) {
+    match obj {
+        Obj::Dir(dir) => encode_dir(dir, w),
+        Obj::Real(r) => encode_real(r, w),
+        Obj::Complex(c) => {
+            encode_real(&c.real, w);
+            encode_real(&c.imag, w);
+        }
+        Obj::Int(v) => encode_zint(v, w),
+        Obj::LongReal(r) => encode_long_real(r, w),
+        Obj::LongComplex(c) => {
+            encode_long_real(&c.real, w);
+            encode_long_real(&c.imag, w);
+        }
+        Obj::CStr(s) => w.put_lv(|w| w.put_bytes(&charset::encode(&s.0))),
+        Obj::Prg(objs) | Obj::List(objs) | Obj::Symb(objs) | Obj::Unit(objs) | Obj::Matrix(objs) => {
+            for o in objs {
+                encode_obj(o, w);
+            }
+            w.put_integer5(SEMI);
+        }
+        Obj::Array(arr) => encode_array(arr, w),
+        Obj::Ext(_) | Obj::Semi() => {}
+        Obj::HexString(h) => w.put_lv(|w| w.put_nibbles(&h.0)),
+        Obj::Grob(g) => w.put_lv(|w| {
+            w.put_integer5usize(g.height);
+            w.put_integer5usize(g.width);
+            w.put_nibbles(&g.data);
+        }),
+        Obj::ExtObj(_, blob, _) | Obj::Code(blob) | Obj::Aplet(blob) | Obj::MiniFont(blob) => {
+            w.put_lv(|w| w.put_nibbles(&blob.0))
+        }
+        Obj::Bint(v) => w.put_integer5(*v),
+        Obj::Char(c) => w.put_integer2(*c),
+        Obj::ExtReal(r) => encode_ext_real(r, w),
+        Obj::ExtComplex(c) => {
+            encode_ext_real(&c.real, w);
+            encode_ext_real(&c.imag, w);
+        }
+        Obj::RomPtr(lib, cmd) => {
+            w.put_integer3(*lib);
+            w.put_integer3(*cmd);
+        }
+        Obj::FlashPtr(bank, cmd) => {
+            w.put_integer3(*bank);
+            w.put_integer4(*cmd);
+        }
+        Obj::GlobalName(s) | Obj::LocalName(s) => w.put_pascal_string(s),
+        Obj::Tagged(tag, obj) => {
+            w.put_pascal_string(tag);
+            encode_obj(obj, w);
+        }
+        Obj::Library(lib) => encode_library(lib, w),
+    }
+}
+
+fn encode_array(arr: &Array, w: &mut NibbleWriter) {
+    w.put_lv(|w| {
+        w.put_integer5(arr.obj_type);
+        w.put_integer5usize(arr.dims.len());
+        for dim in &arr.dims {
+            w.put_integer5usize(*dim);
+        }
+        // linked arrays have a table of offsets to the elements
+        let offsets: Vec<(OffsetField, usize)> = match &arr.links {
+            Some(links) => links
+                .iter()
+                .map(|l| (w.reserve_offset(), l))
+                .filter_map(|(field, l)| l.map(|i| (field, i)))
+                .collect(),
+            None => Vec::new(),
+        };
+        // elements share the prolog of the array, so only their body is written
+        let mut positions = Vec::new();
+        for obj in &arr.objects {
+            positions.push(w.len());
+            encode_body(obj, w);
+        }
+        for (field, i) in offsets {
+            w.patch_offset_to(field, positions[i]);
+        }
+    });
+}
+
+fn encode_dir(dir: &Dir, w: &mut NibbleWriter) {
+    w.put_integer3(dir.attached_libs);
+    let last_entity = w.reserve_offset();
+    w.put_integer5(0);
+    let mut previous_start = None;
+    for entity in &dir.entities {
+        // each entity but the first is preceded by the size of the previous one,
+        // so that the directory can be walked backwards
+        if let Some(start) = previous_start {
+            w.put_integer5usize(w.len() - start);
+        }
+        let start = w.len();
+        w.put_pascal_string(&entity.name);
+        w.put_integer2usize(charset::encode(&entity.name).len());
+        encode_obj(&entity.obj, w);
+        previous_start = Some(start);
+    }
+    if let Some(start) = previous_start {
+        w.patch_offset_to(last_entity, start);
+    }
+}
+
+// the parts of the library are laid out in the same order as in the fixtures:
+// hash table, link table, xlibs, hidden objects, extra objects, config object, message table
+fn encode_library(lib: &Library, w: &mut NibbleWriter) {
+    let size = w.reserve_size();
+    w.put_pascal_string(&lib.name);
+    if !lib.name.is_empty() {
+        w.put_integer2usize(charset::encode(&lib.name).len());
+    }
+    w.put_integer3(lib.number);
+    let hash_table = w.reserve_offset();
+    let message_table = w.reserve_offset();
+    let link_table = w.reserve_offset();
+    let config = w.reserve_offset();
+
+    if !lib.hash_table.cmd_to_name.is_empty() {
+        w.patch_offset(hash_table);
+        encode_hash_table(lib, w);
+    }
+    // the link table is indexed by command number, objects follow in the same order
+    enum Link<'a> {
+        Xlib(&'a Xlib),
+        Hidden(&'a Obj),
+        Config,
+    }
+    let mut entries: Vec<(u16, Link)> = lib.xlib.iter().map(|x| (x.command_number, Link::Xlib(x))).collect();
+    entries.extend(lib.hidden_objects.iter().map(|h| (h.command_number, Link::Hidden(&h.object))));
+    if let (Some(link), Some(_)) = (lib.config_link, &lib.config_object) {
+        entries.push((link, Link::Config));
+    }
+    entries.sort_by_key(|(link, _)| *link);
+    let mut links = Vec::new();
+    if !entries.is_empty() {
+        w.patch_offset(link_table);
+        w.put_integer5(DOHSTR);
+        w.put_lv(|w| {
+            for _ in &entries {
+                links.push(w.reserve_offset());
+            }
+        });
+    }
+    let mut config_link = None;
+    for ((_, entry), link) in entries.iter().zip(links) {
+        match entry {
+            Link::Xlib(xlib) => {
+                // kinds with their high bit set fit in one nibble
+                if (0x8..=0xf).contains(&xlib.kind) {
+                    w.put_integer1(xlib.kind as u8);
+                } else {
+                    w.put_integer3(xlib.kind);
+                }
+                w.put_integer3(xlib.library_number);
+                w.put_integer3(xlib.command_number);
+                w.patch_offset(link);
+                encode_obj(&xlib.object, w);
+            }
+            Link::Hidden(obj) => {
+                w.patch_offset(link);
+                encode_obj(obj, w);
+            }
+            Link::Config => config_link = Some(link),
+        }
+    }
+    // extra objects are not referenced by the library, they are just kept along
+    for file in &lib.extra_objects {
+        w.put_bytes(&file.header);
+        encode_obj(&file.obj, w);
+        w.put_nibbles(&file.padding);
+    }
+    if let Some(config_object) = &lib.config_object {
+        w.patch_offset(config);
+        if let Some(link) = config_link {
+            w.patch_offset(link);
+        }
+        encode_obj(config_object, w);
+    }
+    if !lib.message_table.is_empty() {
+        w.patch_offset(message_table);
+        // linked tables leave empty messages out
+        let mut objects = Vec::new();
+        let mut links = Vec::new();
+        for message in &lib.message_table {
+            if lib.linked_messages && message.is_empty() {
+                links.push(None);
+            } else {
+                links.push(Some(objects.len()));
+                objects.push(Obj::CStr(crate::StringBlob(message.clone())));
+            }
+        }
+        let links = lib.linked_messages.then_some(links);
+        w.put_integer5(if links.is_some() { DOLNKARRY } else { DOARRY });
+        let messages = Array {
+            obj_type: DOCSTR,
+            num_dims: 1,
+            dims: vec![lib.message_table.len()],
+            objects,
+            links,
+        };
+        encode_array(&messages, w);
+    }
+    // the crc covers the library from its size field, so it is written last
+    let crc_pos = w.len();
+    w.put_integer4(0);
+    w.patch_size(size);
+    let crc = hp_crc(&w.as_slice()[size.position()..crc_pos]);
+    w.patch_integer(crc_pos, crc as u64, 4);
+}
+
+// hash table is a binary integer made of 16 offsets to lists of names grouped by length,
+// an offset to the table of names indexed by command number, then those two tables
+fn encode_hash_table(lib: &Library, w: &mut NibbleWriter) {
+    w.put_integer5(DOHSTR);
+    w.put_lv(|w| {
+        let length_tables: Vec<OffsetField> = (0..16).map(|_| w.reserve_offset()).collect();
+        let cmd_table = w.reserve_offset();
+
+        let mut names: Vec<(&u16, &String)> = lib.hash_table.cmd_to_name.iter().collect();
+        let bucket = |name: &str| charset::encode(name).len().clamp(1, 16) - 1;
+        names.sort_by_key(|(cmd, name)| (bucket(name), **cmd));
+        let mut name_pos = std::collections::HashMap::new();
+        let mut current_bucket = None;
+        for (cmd, name) in &names {
+            let b = bucket(name);
+            if current_bucket != Some(b) {
+                w.patch_offset(length_tables[b]);
+                current_bucket = Some(b);
+            }
+            name_pos.insert(**cmd, w.len());
+            w.put_pascal_string(name);
+            w.put_integer3(**cmd);
+        }
+        w.patch_offset(cmd_table);
+        let max_cmd = names.iter().map(|(cmd, _)| **cmd).max().unwrap_or(0);
+        for cmd in 0..=max_cmd {
+            // those offsets point backwards
+            let pos = w.len();
+            w.put_integer5usize(name_pos.get(&cmd).map(|p| pos - p).unwrap_or(0));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{next_obj, parse_hp4x, Xlib};
+    use crate::Real;
+    use winnow::Parser;
+
+    fn reparse(obj: &Obj) -> Obj {
+        let nibs = to_nibbles(obj);
+        next_obj.parse(Nibbles::new(&nibs)).unwrap()
+    }
+
+    #[test]
+    fn test_encode_real() {
+        let r = Obj::Real(Real { exponent: 0x999, mantissa: 0x123456789012, sign: 9 });
+        let nibs = to_nibbles(&r);
+        assert_eq!(nibs.len(), 21);
+        assert_eq!(&nibs[0..5], &[0x3, 0x3, 0x9, 0x2, 0x0]);
+        assert_eq!(&nibs[5..8], &[0x9, 0x9, 0x9]);
+        assert_eq!(nibs[20], 9);
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        let nibs = to_nibbles(&Obj::Int(ZInt::from(-120)));
+        assert_eq!(nibs, vec![0x4, 0x1, 0x6, 0x2, 0x0, 0x9, 0x0, 0x0, 0x0, 0x0, 0x0, 0x2, 0x1, 0x9]);
+        for v in ["0", "7", "-7", "222222222222", "-123456789012345678901234567890123456789012345678901234567890"] {
+            match reparse(&Obj::Int(v.parse().unwrap())) {
+                Obj::Int(r) => assert_eq!(r.to_string(), v),
+                o => panic!("expected int, got {:?}", o),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_hp49_objects() {
+        let long_real = |m: i128, e: i128| LongReal { mantissa: ZInt::from(m), exponent: ZInt::from(e) };
+        let objs = vec![
+            Obj::LongReal(long_real(-15, 3)),
+            Obj::LongComplex(crate::LongComplex { real: long_real(1, 0), imag: long_real(2, -1) }),
+            Obj::Matrix(vec![
+                Obj::Matrix(vec![Obj::GlobalName("X".to_string()), Obj::Int(ZInt::from(2))]),
+                Obj::Matrix(vec![Obj::Int(ZInt::from(3)), Obj::Int(ZInt::from(4))]),
+            ]),
+            Obj::MiniFont(crate::Blob(vec![1, 2, 3])),
+        ];
+        let list = Obj::List(objs);
+        let nibs = to_nibbles(&list);
+        assert_eq!(to_nibbles(&reparse(&list)), nibs);
+    }
+
+    #[test]
+    fn test_encode_arrays() {
+        let real = |v: f64| Obj::Real(Real::from_float(v));
+        let array = Obj::Array(Array {
+            obj_type: DOREAL,
+            num_dims: 2,
+            dims: vec![2, 2],
+            objects: vec![real(1.0), real(2.0), real(3.0), real(4.0)],
+            links: None,
+        });
+        let nibs = to_nibbles(&array);
+        // prolog, size, type, number of dims, 2 dims, 4 reals without prolog
+        assert_eq!(nibs.len(), 6 * 5 + 4 * 16);
+        assert_eq!(to_nibbles(&reparse(&array)), nibs);
+
+        let linked = Obj::Array(Array {
+            obj_type: DOREAL,
+            num_dims: 1,
+            dims: vec![4],
+            objects: vec![real(1.0), real(2.0)],
+            links: Some(vec![Some(0), None, Some(1), Some(0)]),
+        });
+        let nibs = to_nibbles(&linked);
+        assert_eq!(nibs.len(), 5 * 5 + 4 * 5 + 2 * 16);
+        match reparse(&linked) {
+            Obj::Array(arr) => {
+                assert_eq!(arr.links, Some(vec![Some(0), None, Some(1), Some(0)]));
+                assert_eq!(arr.objects.len(), 2);
+                assert_eq!(to_nibbles(&Obj::Array(arr)), nibs);
+            }
+            o => panic!("expected array, got {:?}", o),
+        }
+    }
+
+    #[test]
+    fn test_encode_dir() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/DIR.1");
+        let obj = parse_hp4x(&path).unwrap();
+        match (&obj, reparse(&obj)) {
+            (Obj::Dir(a), Obj::Dir(b)) => {
+                assert_eq!(a.attached_libs, b.attached_libs);
+                let names = |d: &Dir| d.entities.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+                assert_eq!(names(a), names(&b));
+            }
+            o => panic!("expected dirs, got {:?}", o),
+        }
+    }
+
+    // libraries are laid out from their fields, into the same nibbles as the fixtures
+    #[test]
+    fn test_encode_library_layout() {
+        for file in ["BABL49", "extable.HP"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures").join(file);
+            let contents = std::fs::read(&path).unwrap();
+            let obj = parse_hp4x(&path).unwrap();
+            let Obj::Library(lib) = &obj else { panic!("{} is not a library", file) };
+            let nibs = to_nibbles(&obj);
+            assert!(nibs == extract_nibbles(&contents[8..]), "{} is not laid out as the fixture", file);
+            // the crc covers the size field and the body
+            let end = nibs.len() - 4;
+            let crc = nibs[end..].iter().rev().fold(0u16, |acc, n| (acc << 4) | *n as u16);
+            assert_eq!(crc, hp_crc(&nibs[5..end]));
+            assert_eq!(crc, lib.crc);
+        }
+    }
+
+    // edited fields are written back
+    #[test]
+    fn test_encode_edited_library() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/BABL49");
+        let Obj::Library(mut lib) = parse_hp4x(&path).unwrap() else { panic!("expected library") };
+        lib.name = "RENAMED".to_string();
+        lib.message_table = vec!["Bad argument".to_string()];
+        lib.hash_table.name_to_cmd.insert("NEW".to_string(), 9);
+        lib.hash_table.cmd_to_name.insert(9, "NEW".to_string());
+        lib.xlib.push(Xlib { kind: 8, library_number: lib.number, command_number: 9, object: Box::new(Obj::Bint(1)) });
+        let Obj::Library(relib) = reparse(&Obj::Library(lib)) else { panic!("expected library") };
+        assert_eq!(relib.name, "RENAMED");
+        assert_eq!(relib.message_table, vec!["Bad argument".to_string()]);
+        assert!(!relib.linked_messages);
+        assert_eq!(relib.hash_table.name_to_cmd.get("NEW"), Some(&9));
+        let cmds: Vec<_> = relib.xlib.iter().map(|x| (x.kind, x.command_number)).collect();
+        assert_eq!(cmds, vec![(8, 0), (8, 1), (8, 2), (8, 9)]);
+        let hidden: Vec<_> = relib.hidden_objects.iter().map(|h| h.command_number).collect();
+        assert_eq!(hidden, vec![3, 4, 5, 6, 7, 8]);
+        assert!(relib.config_object.is_some());
+    }
+
+    // hidden objects and commands keep their place in the link table
+    #[test]
+    fn test_encode_library_link_order() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/BABL49");
+        let Obj::Library(mut lib) = parse_hp4x(&path).unwrap() else { panic!("expected library") };
+        lib.xlib[1].command_number = 3;
+        lib.hidden_objects[0].command_number = 1;
+        lib.message_table = vec!["A".to_string(), String::new(), "B".to_string()];
+        lib.linked_messages = true;
+        let nibs = to_nibbles(&Obj::Library(lib));
+        let Obj::Library(relib) = next_obj.parse(Nibbles::new(&nibs)).unwrap() else { panic!("expected library") };
+        let cmds: Vec<_> = relib.xlib.iter().map(|x| x.command_number).collect();
+        assert_eq!(cmds, vec![0, 2, 3]);
+        let hidden: Vec<_> = relib.hidden_objects.iter().map(|h| h.command_number).collect();
+        assert_eq!(hidden, vec![1, 4, 5, 6, 7, 8]);
+        assert!(relib.linked_messages);
+        assert_eq!(relib.message_table, vec!["A", "", "B"]);
+        // the message table stays a linked array
+        assert_eq!(to_nibbles(&Obj::Library(relib)), nibs);
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use winnow::{error::{ErrMode, ErrorKind, ParserError}, PResult, stream::Location};
+use winnow::{error::{ErrMode, ErrorKind, ParserError}, PResult};
 
 use crate::{library::Library, nibbles::*, DOEXT3};
 
@@ -31,7 +31,8 @@ impl From<Library> for Extable {
             .extra_objects
             .into_iter()
             .next()
-            .expect("table is in extra objects, but this lib doesn't have it");
+            .expect("table is in extra objects, but this lib doesn't have it")
+            .obj;
         match obj {
             crate::Obj::ExtObj(DOEXT3, nib, _) => {
                 let mut buf = Nibbles::new(&nib.0);
@@ -68,7 +69,7 @@ fn next_entry(nib: &mut Nibbles) -> PResult<Entry> {
 fn extract_entries(all: &mut Nibbles) -> PResult<Vec<Entry>> {
     let _ = integer5(all)?;
     let mut entries = Vec::new();
-    let mut nib = all.clone();
+    let mut nib = *all;
     while nib.len() > 0 {
         if let Ok(entry) = next_entry(&mut nib) {
             entries.push(entry);
@@ -81,19 +82,25 @@ fn extract_entries(all: &mut Nibbles) -> PResult<Vec<Entry>> {
 
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::path::Path;
 
     use super::*;
     use crate::{parse_hp4x, Obj};
+
+    /// extable of the fixture library
+    pub(crate) fn fixture() -> Extable {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/extable.HP");
+        match parse_hp4x(&path).expect("failed to parse extable") {
+            Obj::Library(lib) => Extable::from(lib),
+            obj => panic!("expected library, got {:?}", obj),
+        }
+    }
+
     #[test]
     fn test_extable() {
-        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/extable.HP");
-        let obj = parse_hp4x(&path).expect("failed to parse extable");
-        if let Obj::Library(lib) = obj {
-            let extable = Extable::from(lib);
-            assert_eq!(extable.name_to_addr.len(), 5307);
-            assert_eq!(extable.name_to_addr["xDISP"], 0x3816b);
-        } 
+        let extable = fixture();
+        assert_eq!(extable.name_to_addr.len(), 5307);
+        assert_eq!(extable.name_to_addr["xDISP"], 0x3816b);
     }
 }
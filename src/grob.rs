@@ -0,0 +1,366 @@
+//! Graphic objects (GROB)
+//!
+//! A grob is made of its height and width (5 nibbles each), followed by the bitmap.
+//! Each row is padded to a whole number of bytes, and the pixels of a nibble
+//! are stored from its least significant bit: pixel 0 is bit 0 of the first nibble.
+//! A bit set means a black pixel.
+//!
+//! Grobs can be exported to and imported from PNG and PBM images.
+use winnow::error::StrContext;
+use winnow::{PResult, Parser};
+
+use crate::decompile::Decompiled;
+use crate::nibbles::*;
+use crate::{Error, Extable};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grob {
+    pub height: usize,
+    pub width: usize,
+    /// bitmap nibbles, in memory order
+    pub data: Vec<u8>,
+}
+
+impl Grob {
+    /// blank grob
+    pub fn new(width: usize, height: usize) -> Self {
+        Grob { height, width, data: vec![0; Grob::row_nibbles(width) * height] }
+    }
+    /// number of nibbles of a row, rows are padded to whole bytes
+    pub fn row_nibbles(width: usize) -> usize {
+        width.div_ceil(8) * 2
+    }
+    fn bit(&self, x: usize, y: usize) -> (usize, u8) {
+        (y * Grob::row_nibbles(self.width) + x / 4, 1 << (x % 4))
+    }
+    /// true if the pixel is black
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (nib, mask) = self.bit(x, y);
+        self.data.get(nib).map(|n| n & mask != 0).unwrap_or(false)
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, black: bool) {
+        let (nib, mask) = self.bit(x, y);
+        if let Some(n) = self.data.get_mut(nib) {
+            if black {
+                *n |= mask;
+            } else {
+                *n &= !mask;
+            }
+        }
+    }
+    /// binary PBM (P4) image
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut out = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        for y in 0..self.height {
+            for x0 in (0..self.width).step_by(8) {
+                let mut b = 0u8;
+                for x in x0..(x0 + 8).min(self.width) {
+                    if self.pixel(x, y) {
+                        b |= 0x80 >> (x - x0);
+                    }
+                }
+                out.push(b);
+            }
+        }
+        out
+    }
+    /// 1 bit grayscale PNG image
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::One);
+            let mut writer = encoder.write_header().map_err(|e| Error::ImageError(e.to_string()))?;
+            // in png grayscale, 0 is black
+            let mut rows = Vec::new();
+            for y in 0..self.height {
+                for x0 in (0..self.width).step_by(8) {
+                    let mut b = 0u8;
+                    for x in x0..x0 + 8 {
+                        if x >= self.width || !self.pixel(x, y) {
+                            b |= 0x80 >> (x - x0);
+                        }
+                    }
+                    rows.push(b);
+                }
+            }
+            writer.write_image_data(&rows).map_err(|e| Error::ImageError(e.to_string()))?;
+        }
+        Ok(out)
+    }
+}
+
+/// How gray levels are turned into black and white pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Monochrome {
+    /// pixels darker than the level are black
+    Threshold(u8),
+    /// Floyd-Steinberg error diffusion
+    Dither,
+}
+
+impl Default for Monochrome {
+    fn default() -> Self {
+        Monochrome::Threshold(128)
+    }
+}
+
+impl Grob {
+    /// grob from 8 bit gray levels (0 is black), one byte per pixel, row after row
+    pub fn from_gray(width: usize, height: usize, gray: &[u8], mode: Monochrome) -> Result<Self, Error> {
+        if width.checked_mul(height).is_none_or(|size| gray.len() < size) {
+            return Err(Error::ImageError(format!("{} gray levels for a {}x{} image", gray.len(), width, height)));
+        }
+        let mut g = Grob::new(width, height);
+        match mode {
+            Monochrome::Threshold(level) => {
+                for y in 0..height {
+                    for x in 0..width {
+                        g.set_pixel(x, y, gray[y * width + x] < level);
+                    }
+                }
+            }
+            Monochrome::Dither => {
+                let mut levels: Vec<i32> = gray.iter().map(|&l| l as i32).collect();
+                for y in 0..height {
+                    for x in 0..width {
+                        let old = levels[y * width + x];
+                        let black = old < 128;
+                        g.set_pixel(x, y, black);
+                        let err = old - if black { 0 } else { 255 };
+                        let mut spread = |dx: isize, dy: usize, weight: i32| {
+                            let nx = x as isize + dx;
+                            if nx >= 0 && (nx as usize) < width && y + dy < height {
+                                levels[(y + dy) * width + nx as usize] += err * weight / 16;
+                            }
+                        };
+                        spread(1, 0, 7);
+                        spread(-1, 1, 3);
+                        spread(0, 1, 5);
+                        spread(1, 1, 1);
+                    }
+                }
+            }
+        }
+        Ok(g)
+    }
+
+    /// grob from a PNG image, transparent pixels are white
+    pub fn from_png(bytes: &[u8], mode: Monochrome) -> Result<Self, Error> {
+        let image_error = |e: png::DecodingError| Error::ImageError(e.to_string());
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(image_error)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(image_error)?;
+        let (width, height) = (info.width as usize, info.height as usize);
+        let channels = info.color_type.samples();
+        let gray: Vec<u8> = buf[..info.buffer_size()]
+            .chunks(info.line_size)
+            .flat_map(|line| line[..width * channels].chunks(channels))
+            .map(|px| {
+                let (luma, alpha) = match info.color_type {
+                    png::ColorType::Grayscale => (px[0] as u32, 255),
+                    png::ColorType::GrayscaleAlpha => (px[0] as u32, px[1] as u32),
+                    png::ColorType::Rgb | png::ColorType::Indexed => (luma(px), 255),
+                    png::ColorType::Rgba => (luma(px), px[3] as u32),
+                };
+                ((luma * alpha + 255 * (255 - alpha)) / 255) as u8
+            })
+            .collect();
+        Grob::from_gray(width, height, &gray, mode)
+    }
+
+    /// grob from a binary (P4) or plain (P1) PBM image
+    pub fn from_pbm(bytes: &[u8]) -> Result<Self, Error> {
+        let bad = |what: &str| Error::ImageError(format!("bad PBM: {}", what));
+        let mut pos = 0;
+        let token = |pos: &mut usize| -> Option<&[u8]> {
+            loop {
+                while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+                    *pos += 1;
+                }
+                if *pos < bytes.len() && bytes[*pos] == b'#' {
+                    while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                        *pos += 1;
+                    }
+                } else {
+                    break;
+                }
+            }
+            let start = *pos;
+            while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            (start < *pos).then(|| &bytes[start..*pos])
+        };
+        let magic = token(&mut pos).ok_or_else(|| bad("no magic"))?.to_vec();
+        let number = |pos: &mut usize| -> Result<usize, Error> {
+            token(pos)
+                .and_then(|t| std::str::from_utf8(t).ok())
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| bad("bad size"))
+        };
+        let width = number(&mut pos)?;
+        let height = number(&mut pos)?;
+        // the bitmap must fit in the file, which also bounds the allocation
+        let needed = match &magic[..] {
+            b"P4" => width.div_ceil(8).checked_mul(height),
+            _ => width.checked_mul(height),
+        };
+        if needed.is_none_or(|n| n > bytes.len() - pos) {
+            return Err(bad("truncated"));
+        }
+        let mut g = Grob::new(width, height);
+        match &magic[..] {
+            b"P4" => {
+                // a single whitespace separates the header from the bitmap
+                let data = bytes.get(pos + 1..).unwrap_or_default();
+                let row_bytes = width.div_ceil(8);
+                if data.len() < row_bytes * height {
+                    return Err(bad("truncated"));
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        g.set_pixel(x, y, data[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0);
+                    }
+                }
+            }
+            b"P1" => {
+                let mut bits = bytes[pos..].iter().filter(|b| matches!(b, b'0' | b'1'));
+                for y in 0..height {
+                    for x in 0..width {
+                        let bit = bits.next().ok_or_else(|| bad("truncated"))?;
+                        g.set_pixel(x, y, *bit == b'1');
+                    }
+                }
+            }
+            _ => return Err(bad("not a P1 or P4 image")),
+        }
+        Ok(g)
+    }
+}
+
+fn luma(rgb: &[u8]) -> u32 {
+    (rgb[0] as u32 * 299 + rgb[1] as u32 * 587 + rgb[2] as u32 * 114) / 1000
+}
+
+/// User RPL form: GROB width height data
+impl Decompiled for Grob {
+    fn decompile(&self, _extable: &Extable) -> String {
+        let data: String = self.data.iter().map(|n| format!("{:X}", n)).collect();
+        format!("GROB {} {} {}", self.width, self.height, data)
+    }
+}
+
+pub(crate) fn next_grob(input: &mut Nibbles) -> PResult<Grob> {
+    _next_grob.context(StrContext::Label("grob")).parse_next(input)
+}
+fn _next_grob(input: &mut Nibbles) -> PResult<Grob> {
+    let mut data = next_lv(input)?;
+    let height = integer5usize(&mut data)?;
+    let width = integer5usize(&mut data)?;
+    Ok(Grob { height, width, data: data.to_vec() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5x2 grob, with an X like pattern
+    fn sample() -> Grob {
+        let mut g = Grob::new(5, 2);
+        g.set_pixel(0, 0, true);
+        g.set_pixel(4, 0, true);
+        g.set_pixel(2, 1, true);
+        g
+    }
+
+    #[test]
+    fn test_grob_layout() {
+        let g = sample();
+        assert_eq!(g.data, vec![0x1, 0x1, 0x4, 0x0]);
+        assert!(g.pixel(4, 0));
+        assert!(!g.pixel(3, 0));
+        assert_eq!(g.decompile(&Extable::default()), "GROB 5 2 1140");
+    }
+
+    #[test]
+    fn test_next_grob() {
+        let mut w = NibbleWriter::new();
+        w.put_lv(|w| {
+            w.put_integer5usize(2);
+            w.put_integer5usize(5);
+            w.put_nibbles(&[0x1, 0x1, 0x4, 0x0]);
+        });
+        let g = next_grob.parse(Nibbles::new(w.as_slice())).unwrap();
+        assert_eq!(g, sample());
+    }
+
+    #[test]
+    fn test_to_pbm() {
+        assert_eq!(sample().to_pbm(), b"P4\n5 2\n\x88\x20");
+    }
+
+    #[test]
+    fn test_to_png() {
+        let png = sample().to_png().unwrap();
+        let decoder = png::Decoder::new(&png[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (5, 2));
+        assert_eq!(info.bit_depth, png::BitDepth::One);
+        assert_eq!(&buf[..2], &[0x77, 0xdf]);
+    }
+
+    #[test]
+    fn test_image_roundtrip() {
+        let g = sample();
+        assert_eq!(Grob::from_pbm(&g.to_pbm()).unwrap(), g);
+        assert_eq!(Grob::from_png(&g.to_png().unwrap(), Monochrome::default()).unwrap(), g);
+    }
+
+    #[test]
+    fn test_from_plain_pbm() {
+        let pbm = b"P1\n# comment\n5 2\n1 0 0 0 1\n0 0 1 0 0\n";
+        assert_eq!(Grob::from_pbm(pbm).unwrap(), sample());
+        assert!(Grob::from_pbm(b"P1\n5 2\n1 0").is_err());
+        assert!(Grob::from_pbm(b"P5\n5 2\n").is_err());
+        // huge sizes are rejected before allocating
+        assert!(Grob::from_pbm(b"P4\n4000000000 4000000000\n").is_err());
+        assert!(Grob::from_pbm(b"P1\n18446744073709551615 2\n").is_err());
+    }
+
+    #[test]
+    fn test_from_gray() {
+        let gray = [0, 100, 200, 255];
+        let g = Grob::from_gray(4, 1, &gray, Monochrome::Threshold(128)).unwrap();
+        assert_eq!(g.data, vec![0x3, 0x0]);
+        // too few gray levels for the size
+        assert!(Grob::from_gray(4, 2, &gray, Monochrome::Threshold(128)).is_err());
+        assert!(Grob::from_gray(4, 2, &gray, Monochrome::Dither).is_err());
+        assert!(Grob::from_gray(usize::MAX, 2, &gray, Monochrome::Dither).is_err());
+        // mid gray dithers to half the pixels
+        let g = Grob::from_gray(16, 16, &[128; 256], Monochrome::Dither).unwrap();
+        let black = (0..16).flat_map(|y| (0..16).map(move |x| (x, y))).filter(|&(x, y)| g.pixel(x, y)).count();
+        assert!((96..=160).contains(&black), "{}", black);
+    }
+
+    #[test]
+    fn test_from_rgba_png() {
+        let mut png = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut png, 3, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            // black, white, transparent black
+            writer.write_image_data(&[0, 0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 0]).unwrap();
+        }
+        let g = Grob::from_png(&png, Monochrome::default()).unwrap();
+        assert_eq!((g.width, g.height), (3, 1));
+        assert_eq!(g.data, vec![0x1, 0x0]);
+    }
+}
@@ -6,15 +6,36 @@ mod basic;
 mod dir;
 mod extable;
 mod library;
+mod grob;
+mod unit;
+mod symbolic;
+mod render;
+pub mod commands;
+mod sysrpl;
+pub mod control;
+pub mod locals;
+pub mod disasm;
+pub mod asm;
+pub mod compile;
+pub mod transfer;
+pub mod trigraph;
+pub mod asc;
 pub mod decompile;
+pub mod charset;
+pub mod encode;
 use nibbles::*;
-use basic::*;
+pub use nibbles::NibbleWriter;
+pub use basic::*;
 pub use dir::*;
 use library::*;
 pub use extable::*;
+pub use grob::*;
+pub use unit::*;
+pub use symbolic::*;
 
-use winnow::combinator::{opt, repeat};
+use winnow::combinator::repeat;
 use winnow::error::{ErrorKind, ParserError, StrContext};
+use winnow::stream::Location;
 use winnow::token::take;
 use winnow::{PResult, Parser};
 use std::fmt::Debug;
@@ -30,6 +51,12 @@ pub enum Error {
     IoError(#[from] std::io::Error),
     #[error("Parse Error: {0}")]
     ParseError(String),
+    #[error("Image Error: {0}")]
+    ImageError(String),
+    #[error("Assembly Error: {0}")]
+    AssemblyError(String),
+    #[error("Compile Error: {0}")]
+    CompileError(String),
 }
 type Result<T> = std::result::Result<T, Error>;
 
@@ -40,11 +67,8 @@ fn _next_semi_terminated(nibs: &mut Nibbles) -> PResult<Vec<Obj>> {
     let mut objs = Vec::new();
     loop {
         let obj = next_obj(nibs)?;
-        match obj {
-            Obj::Semi() => {
-                break;
-            }
-            _ => {}
+        if let Obj::Semi() = obj {
+            break;
         }
         objs.push(obj);
     }
@@ -58,8 +82,19 @@ pub struct Array {
     pub num_dims: usize,
     pub dims: Vec<usize>,
     pub objects: Vec<Obj>,
+    /// linked arrays only: for each element, the index of its object in `objects`,
+    /// or None if the element is missing. Several elements can share the same object.
+    pub links: Option<Vec<Option<usize>>>,
+}
+impl Array {
+    /// elements in row major order, resolving the links of linked arrays
+    pub fn elements(&self) -> Vec<Option<&Obj>> {
+        match &self.links {
+            Some(links) => links.iter().map(|l| l.and_then(|i| self.objects.get(i))).collect(),
+            None => self.objects.iter().map(Some).collect(),
+        }
+    }
 }
-
 
 pub(crate) fn next_array(input: &mut Nibbles) -> PResult<Array> {
     _next_array.context(StrContext::Label("array")).parse_next(input)
@@ -69,21 +104,60 @@ fn _next_array(input: &mut Nibbles) -> PResult<Array> {
     let obj_type = integer5(input)?;
     let num_dims = integer5usize(input)?;
     let dims: Vec<usize> = repeat(num_dims, integer5usize).parse_next(input)?;
-    let num_objs = dims.iter().fold(1, |acc, x| acc * x);
+    let num_objs = dims.iter().product::<usize>();
     let mut objects = Vec::new();
     for _ in 0..num_objs {
         objects.push(next_obj_with_prolog(input, obj_type)?);
     }
-    Ok(Array{obj_type, num_dims, dims, objects})
+    Ok(Array{obj_type, num_dims, dims, objects, links: None})
+}
+
+pub(crate) fn next_linked_array(input: &mut Nibbles) -> PResult<Array> {
+    _next_linked_array.context(StrContext::Label("linked array")).parse_next(input)
+}
+// linked arrays have a table of offsets to their elements, followed by the elements
+fn _next_linked_array(input: &mut Nibbles) -> PResult<Array> {
+    let mut data = next_lv(input)?;
+    let obj_type = integer5(&mut data)?;
+    let num_dims = integer5usize(&mut data)?;
+    let dims: Vec<usize> = repeat(num_dims, integer5usize).parse_next(&mut data)?;
+    let num_links = dims.iter().product::<usize>();
+    let targets: Vec<Option<Nibbles>> = repeat(num_links, next_offset).parse_next(&mut data)?;
+    let mut objects = Vec::new();
+    let mut locations = Vec::new();
+    while !data.is_empty() {
+        locations.push(data.location());
+        objects.push(next_obj_with_prolog(&mut data, obj_type)?);
+    }
+    let mut links = Vec::new();
+    for target in targets {
+        let link = match target {
+            Some(target) => match locations.iter().position(|l| *l == target.location()) {
+                Some(i) => Some(i),
+                None => return Err(winnow::error::ErrMode::Cut(ParserError::from_error_kind(&target, ErrorKind::Verify))),
+            },
+            None => None,
+        };
+        links.push(link);
+    }
+    Ok(Array{obj_type, num_dims, dims, objects, links: Some(links)})
 }
 
-pub fn next_integer(input: &mut Nibbles) -> PResult<i32> {
+pub fn next_integer(input: &mut Nibbles) -> PResult<ZInt> {
     _next_integer.context(StrContext::Label("integer")).parse_next(input)
 }
-fn _next_integer(input: &mut Nibbles) -> PResult<i32> {
-    let mut nib = next_lv(input)?;
-    let digits = decode_bcd(&mut nib)?;
-    Ok(digits as i32)
+// precision integers are stored as bcd digits, least significant digit first,
+// followed by a sign nibble (0 for positive, 9 for negative)
+fn _next_integer(input: &mut Nibbles) -> PResult<ZInt> {
+    let nib = next_lv(input)?;
+    if nib.is_empty() {
+        return Err(winnow::error::ErrMode::Cut(ParserError::from_error_kind(input, ErrorKind::Eof)));
+    }
+    let (sign, digits) = nib.split_last().unwrap();
+    Ok(ZInt {
+        negative: *sign == 9,
+        digits: digits.iter().rev().copied().collect(),
+    })
 }
 pub struct Blob(Vec<u8>);
 impl Debug for Blob {
@@ -107,24 +181,40 @@ impl Debug for StringBlob {
 pub enum Obj {
     Dir(Dir),
     Real(Real),
-    Int(i32),
+    Int(ZInt),
     CStr(StringBlob),
     Prg(Vec<Obj>),
     List(Vec<Obj>),
     Symb(Vec<Obj>),
     Unit(Vec<Obj>),
     Complex(Complex),
+    LongReal(LongReal),
+    LongComplex(LongComplex),
+    /// symbolic matrix, rows of a 2 dimensional matrix are themselves symbolic matrices
+    Matrix(Vec<Obj>),
     Array(Array),
-    Integer(i128), 
     Ext(u32),
     ExtObj(u32, Blob, String),
-    FixedObj(u32, Blob, String),
+    /// system binary integer
+    Bint(u32),
+    /// character, in the calculator charset
+    Char(u8),
+    ExtReal(ExtReal),
+    ExtComplex(ExtComplex),
+    /// library number, command number
+    RomPtr(u16, u16),
+    /// flash bank, command number
+    FlashPtr(u16, u16),
     Code(Blob),
     GlobalName(String),
     LocalName(String),
-    Tagged(String),
+    Tagged(String, Box<Obj>),
     Semi(),
     Library(Library),
+    HexString(HexString),
+    Grob(Grob),
+    Aplet(Blob),
+    MiniFont(Blob),
 }
 
 pub(crate) fn next_obj(nibs: &mut Nibbles) -> PResult<Obj> {
@@ -139,106 +229,158 @@ pub(crate) fn next_obj_with_prolog(nibs: &mut Nibbles, prolog: u32 ) -> PResult<
         DORRP => {
             //Dir
             let d = next_dir(nibs)?;
-            return Ok(Obj::Dir(d));
+            Ok(Obj::Dir(d))
         }
         DOREAL => next_real.map(Obj::Real).parse_next(nibs),
         DOCMP => next_complex.map(Obj::Complex).parse_next(nibs),
 
-        DOFLASHP | DOBINT  | DOEREAL | DOECMP | DOCHAR | DOROMP => {
-            let size: usize = match prolog {
-                DOFLASHP => 7,
-                DOBINT => 5,
-                DOREAL => 16,
-                DOEREAL => 21,
-                DOCMP => 32,
-                DOECMP => 42,
-                DOCHAR => 2,
-                DOROMP => 6,
+        DOEREAL => next_ext_real.map(Obj::ExtReal).parse_next(nibs),
+        DOECMP => next_ext_complex.map(Obj::ExtComplex).parse_next(nibs),
+        DOBINT => integer5.map(Obj::Bint).parse_next(nibs),
+        DOCHAR => integer2.map(Obj::Char).parse_next(nibs),
+        DOROMP => (integer3, integer3).map(|(lib, cmd)| Obj::RomPtr(lib, cmd)).parse_next(nibs),
+        DOFLASHP => (integer3, integer4).map(|(bank, cmd)| Obj::FlashPtr(bank, cmd)).parse_next(nibs),
+        DOINT => next_integer.map(Obj::Int).parse_next(nibs),
+        DOLNGREAL => next_long_real.map(Obj::LongReal).parse_next(nibs),
+        DOLNGCMP => next_long_complex.map(Obj::LongComplex).parse_next(nibs),
+        DOAPLET | DOMINIFONT => {
+            let data = next_lv(nibs)?;
+            Ok(match prolog {
+                DOAPLET => Obj::Aplet(Blob(data.to_vec())),
+                DOMINIFONT => Obj::MiniFont(Blob(data.to_vec())),
                 _ => unreachable!(),
-            };
-            let data = take(size).parse_next(nibs)?;
-            return Ok(Obj::FixedObj(prolog, Blob(data.to_vec()), prolog_to_id(prolog).to_owned()));
+            })
         }
-        DOINT => next_integer.map(Obj::Int).parse_next(nibs),
         DOCSTR => {
             let sz = integer5usize(nibs)?;
             let sz = sz - 5;
             let cstr = take(sz).parse_next(nibs)?;
             let bytes = nibbles_to_bytes(&Nibbles::new(cstr))?;
-            // TODO: create an actual codec for hp4x charset. 
-            return Ok(Obj::CStr(StringBlob(String::from_utf8_lossy(&bytes).to_string())));
+            Ok(Obj::CStr(StringBlob(charset::decode(&bytes))))
         }
-        DOCOL | DOLIST | DOSYMB | DOEXT => {
+        DOCOL | DOLIST | DOSYMB | DOEXT | DOMATRIX => {
             let objs = next_semi_terminated(nibs)?;
             let obj = match prolog {
                 DOCOL => Obj::Prg(objs),
                 DOLIST => Obj::List(objs),
                 DOSYMB => Obj::Symb(objs),
                 DOEXT => Obj::Unit(objs),
+                DOMATRIX => Obj::Matrix(objs),
                 _ => unreachable!(),
             };
-            return Ok(obj);
+            Ok(obj)
         }
         DOCODE => {
             let sz = integer5usize(nibs)?;
             let sz = sz - 5;
             let code = take(sz).parse_next(nibs)?;
-            return Ok(Obj::Code(Blob(code.to_vec())));
+            Ok(Obj::Code(Blob(code.to_vec())))
         }
         SEMI => {
-            return Ok(Obj::Semi());
+            Ok(Obj::Semi())
         }
-        DOEXT1 | DOEXT2 | DOEXT3 | DOEXT4 | DOGROB | DOARRY | DOLNKARRY | DOHSTR | DOLIB
-        | DOBAK | DOEXT0 => {
+        DOARRY => next_array.map(Obj::Array).parse_next(nibs),
+        DOLNKARRY => next_linked_array.map(Obj::Array).parse_next(nibs),
+        DOGROB => next_grob.map(Obj::Grob).parse_next(nibs),
+        DOEXT1 | DOEXT2 | DOEXT3 | DOEXT4 | DOHSTR | DOLIB | DOBAK | DOEXT0 => {
             let mut data = next_lv(nibs)?;
             match prolog {
-                DOEXT1 | DOEXT2 | DOEXT3 | DOEXT4 | DOGROB | DOARRY | DOLNKARRY | DOHSTR
-                | DOBAK | DOEXT0 => {
-                    return Ok(Obj::ExtObj(prolog, Blob(data.to_vec()), prolog_to_id(prolog).to_owned()));
+                DOHSTR => Ok(Obj::HexString(HexString(data.to_vec()))),
+                DOEXT1 | DOEXT2 | DOEXT3 | DOEXT4 | DOBAK | DOEXT0 => {
+                    Ok(Obj::ExtObj(prolog, Blob(data.to_vec()), prolog_to_id(prolog).to_owned()))
                 }
                 DOLIB => {
                     let lib = next_library(&mut data)?;
-                    return Ok(Obj::Library(lib));
+                    Ok(Obj::Library(lib))
                 
                 }
                 _ => unreachable!(),
             }
         }
-        DOIDNT | DOLAM | DOTAG => {
+        DOIDNT | DOLAM => {
             let data = pascal_string(nibs)?;
-            return Ok(match prolog {
+            Ok(match prolog {
                 DOIDNT => Obj::GlobalName(data),
                 DOLAM => Obj::LocalName(data),
-                DOTAG => Obj::Tagged(data),
                 _ => unreachable!(),
-            });
+            })
+        }
+        DOTAG => {
+            // the tag is followed by the tagged object
+            let tag = pascal_string(nibs)?;
+            let obj = next_obj(nibs)?;
+            Ok(Obj::Tagged(tag, Box::new(obj)))
         }
         0..0x1000 => {
-            return Err(winnow::error::ErrMode::Cut(ParserError::from_error_kind(nibs, ErrorKind::Verify)));
+            Err(winnow::error::ErrMode::Cut(ParserError::from_error_kind(nibs, ErrorKind::Verify)))
         }
         _ => {
-            return Ok(Obj::Ext(prolog));
+            Ok(Obj::Ext(prolog))
         }
     }
 }
 
 // due to byte encoding, some objects end with a random one nibble padding
-fn next_obj_maybe_1_padding(nibs: &mut Nibbles) -> PResult<Obj> {
+// the remaining nibbles are returned so that the file can be written back as is
+fn next_obj_maybe_1_padding(nibs: &mut Nibbles) -> PResult<(Obj, Vec<u8>)> {
     let obj = next_obj(nibs)?;
-    if nibs.len() > 0 {
-        _ = take(nibs.len()).parse_next(nibs)?;
+    let padding = take(nibs.len()).parse_next(nibs)?;
+    Ok((obj, padding.to_vec()))
+}
+
+/// A binary transfer file
+#[derive(Debug)]
+pub struct Hp4xFile {
+    /// 8 bytes header of the file, e.g. `b"HPHP49-C"`
+    pub header: [u8; 8],
+    pub obj: Obj,
+    /// nibbles found after the object, usually a padding nibble
+    pub padding: Vec<u8>,
+}
+impl Hp4xFile {
+    pub fn new(obj: Obj, header: &[u8; 8]) -> Self {
+        Hp4xFile { header: *header, obj, padding: Vec::new() }
+    }
+    /// Encode the file content
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = NibbleWriter::new();
+        encode::encode_obj(&self.obj, &mut w);
+        w.put_nibbles(&self.padding);
+        let mut bytes = self.header.to_vec();
+        // files are made of bytes, so odd objects are padded with one nibble
+        bytes.extend(w.to_bytes());
+        bytes
     }
-    Ok(obj)
 }
-pub fn parse_hp4x(path: &Path) -> Result<Obj> {
+
+pub fn parse_hp4x_file(path: &Path) -> Result<Hp4xFile> {
     // read the file
     let file_contents = std::fs::read(path)?;
+    if file_contents.len() < 8 {
+        return Err(Error::BadHeader(String::from_utf8_lossy(&file_contents).to_string()));
+    }
     let romrev_header = &file_contents[0..6];
     if romrev_header != b"HPHP48" && romrev_header != b"HPHP49" {
         return Err(Error::BadHeader(String::from_utf8_lossy(romrev_header).to_string()));
     }
+    let header = file_contents[0..8].try_into().unwrap();
     let nibble_array = extract_nibbles(&file_contents[8..]);
-    next_obj_maybe_1_padding.parse(Nibbles::new(&nibble_array[..])).map_err(|e| Error::ParseError(e.to_string()))
+    let (obj, padding) = next_obj_maybe_1_padding
+        .parse(Nibbles::new(&nibble_array[..]))
+        .map_err(|e| Error::ParseError(e.to_string()))?;
+    Ok(Hp4xFile { header, obj, padding })
+}
+pub fn parse_hp4x(path: &Path) -> Result<Obj> {
+    Ok(parse_hp4x_file(path)?.obj)
+}
+
+/// Write a binary transfer file
+pub fn write_hp4x(path: &Path, file: &Hp4xFile) -> Result<()> {
+    if &file.header[0..6] != b"HPHP48" && &file.header[0..6] != b"HPHP49" {
+        return Err(Error::BadHeader(String::from_utf8_lossy(&file.header[0..6]).to_string()));
+    }
+    std::fs::write(path, file.to_bytes())?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -263,6 +405,37 @@ mod tests {
             }
         }
     }
+    // every fixture must be encoded back to the exact same bytes
+    #[test]
+    fn test_roundtrip_fixtures() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures");
+        for entry in std::fs::read_dir(fixtures).unwrap() {
+            let path = entry.unwrap().path();
+            let file = parse_hp4x_file(&path).unwrap_or_else(|e| panic!("{:?}: {:?}", path, e));
+            let contents = std::fs::read(&path).unwrap();
+            assert!(file.to_bytes() == contents, "{:?} does not round-trip", path);
+        }
+    }
+    #[test]
+    fn test_write_pads_odd_objects() {
+        let file = Hp4xFile::new(Obj::GlobalName("A".to_string()), b"HPHP49-C");
+        // prolog(5) + length(2) + 'A'(2) = 9 nibbles, padded to 5 bytes
+        assert_eq!(file.to_bytes(), b"HPHP49-C\x48\x2e\x10\x10\x04");
+    }
+    #[test]
+    fn test_write_grob() {
+        let mut grob = Grob::new(3, 2);
+        grob.set_pixel(1, 1, true);
+        let file = Hp4xFile::new(Obj::Grob(grob.clone()), b"HPHP49-C");
+        let bytes = file.to_bytes();
+        // prolog(5) + length(5) + height(5) + width(5) + 2 rows of 2 nibbles
+        assert_eq!(bytes.len(), 8 + 12);
+        let obj = next_obj.parse(Nibbles::new(&extract_nibbles(&bytes[8..]))).unwrap();
+        match obj {
+            Obj::Grob(g) => assert_eq!(g, grob),
+            _ => panic!("not a grob: {:?}", obj),
+        }
+    }
     // BABL49
     #[test]
     fn test_babal49() {
@@ -289,6 +462,21 @@ mod tests {
             }
         }
     }
+    // TAGGED.49
+    #[test]
+    fn test_tagged() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/TAGGED.49");
+        match parse_hp4x(&path) {
+            Ok(Obj::Tagged(tag, obj)) => {
+                assert_eq!(tag, "label");
+                match *obj {
+                    Obj::Real(r) => assert_eq!(r.as_float(), 42.0),
+                    o => panic!("expected real, got {:?}", o),
+                }
+            }
+            o => panic!("expected tagged object, got {:?}", o),
+        }
+    }
     // Dir.1
     #[test]
     fn test_dir1() {
@@ -135,12 +135,15 @@
 
 use std::collections::HashMap;
 
-use crate::next_array;
+use crate::charset;
+use crate::consts::{DOARRY, DOLNKARRY};
+use crate::{next_array, next_linked_array};
+use crate::Hp4xFile;
 use crate::next_obj;
 use crate::nibbles::*;
 use crate::Obj;
 use winnow::combinator::repeat;
-use winnow::error::StrContext;
+use winnow::error::{ErrMode, ErrorKind, ParserError, StrContext};
 use winnow::stream::Location;
 use winnow::token::take;
 use winnow::PResult;
@@ -153,17 +156,29 @@ pub struct Xlib {
     pub command_number: u16,
     pub object: Box<Obj>,
 }
+/// object of the link table that is not a command of the library
+#[derive(Debug)]
+pub struct HiddenObject {
+    /// index in the link table
+    pub command_number: u16,
+    pub object: Obj,
+}
 #[derive(Debug)]
 pub struct Library {
     pub name: String,
     pub number: u16,
 
     pub message_table: Vec<String>,
+    /// the message table is a linked array instead of an array
+    pub linked_messages: bool,
     pub hash_table: HashTable,
     pub xlib: Vec<Xlib>,
-    pub hidden_objects: Vec<Obj>,
+    pub hidden_objects: Vec<HiddenObject>,
     pub config_object: Option<Box<Obj>>,
-    pub extra_objects: Vec<Obj>,
+    /// transfer files embedded between the objects, e.g. the table of the extable library
+    pub extra_objects: Vec<Hp4xFile>,
+    /// index of the config object in the link table, if it is listed there
+    pub config_link: Option<u16>,
     pub crc: u16,
 }
 #[derive(Debug, Default)]
@@ -173,29 +188,40 @@ pub struct HashTable {
     pub cmd_to_name: HashMap<u16, String>,
 }
 
-fn next_message_table(nib: &mut Nibbles) -> PResult<Vec<String>> {
+// the library does not have the expected layout
+fn malformed<T>(nib: &Nibbles) -> PResult<T> {
+    Err(ErrMode::Cut(ParserError::from_error_kind(nib, ErrorKind::Verify)))
+}
+
+fn next_message_table(nib: &mut Nibbles) -> PResult<(Vec<String>, bool)> {
     _next_message_table.context(StrContext::Label("message table")).parse_next(nib)
 }
-fn _next_message_table(nib: &mut Nibbles) -> PResult<Vec<String>> {
+fn _next_message_table(nib: &mut Nibbles) -> PResult<(Vec<String>, bool)> {
+    // messages are either in an array or in a linked array
     let prolog = integer5(nib)?;
-    assert_eq!(prolog, 0x029E8);
-    let array = next_array(nib)?;
-    let messages = array
-        .objects
-        .iter()
-        .map(|x| match x {
-            Obj::CStr(s) => s.0.clone(),
-            _ => panic!("expected string in message table"),
-        })
-        .collect();
-    Ok(messages)
+    let array = match prolog {
+        DOLNKARRY => next_linked_array(nib)?,
+        DOARRY => next_array(nib)?,
+        _ => return malformed(nib),
+    };
+    let mut messages = Vec::new();
+    for element in array.elements() {
+        match element {
+            Some(Obj::CStr(s)) => messages.push(s.0.clone()),
+            None => messages.push(String::new()),
+            _ => return malformed(nib),
+        }
+    }
+    Ok((messages, prolog == DOLNKARRY))
 }
 fn next_link_table<'a>(nib: &mut Nibbles<'a>) -> PResult<Vec<Nibbles<'a>>> {
     _next_link_table.context(StrContext::Label("link table")).parse_next(nib)
 }
 fn _next_link_table<'a>(nib: &mut Nibbles<'a>) -> PResult<Vec<Nibbles<'a>>> {
     let prolog = integer5(nib)?;
-    assert_eq!(prolog, 0x02A4E);
+    if prolog != 0x02A4E {
+        return malformed(nib);
+    }
     let size = integer5usize(nib)?;
     let num_links = (size / 5) - 1;
     let links = repeat(num_links, next_mandatory_offset).parse_next(nib)?;
@@ -207,7 +233,9 @@ fn next_hash_table(nib: &mut Nibbles) -> PResult<HashTable> {
 fn _next_hash_table(nib: &mut Nibbles) -> PResult<HashTable> {
     let (prolog, mut blob) = next_tlv(nib)?;
     let nib = &mut blob;
-    assert_eq!(prolog, 0x02A4E);
+    if prolog != 0x02A4E {
+        return malformed(nib);
+    }
     let mut name_to_cmd = HashMap::new();
     let mut cmd_to_name = HashMap::new();
 
@@ -227,7 +255,7 @@ fn _next_hash_table(nib: &mut Nibbles) -> PResult<HashTable> {
         let mut nib = buffer;
         while nib.location() < last_position {
             let name = pascal_string(&mut nib)?;
-            if name.len() == 0 {
+            if name.is_empty() {
                 break;
             }
 
@@ -250,7 +278,9 @@ fn _next_hash_table(nib: &mut Nibbles) -> PResult<HashTable> {
 fn find_xlib_header(xlib_object: &Nibbles) -> PResult<(u16, u16, u16)> {
     let mut prev_nibbles = previous_nibbles(xlib_object, 9)?;
     let kind = prev_nibbles[2];
-    let kind = if kind & 0x8 == 0 {
+    // a kind nibble with its high bit set is a 1 nibble kind, e.g. 8 in BABL49 and extable:
+    // the 2 nibbles before it are the end of the previous object
+    let kind = if kind & 0x8 != 0 {
         // in this case, we fetch 2 nibbles too many
         let _ = integer2(&mut prev_nibbles)?;
         integer1(&mut prev_nibbles)? as u16
@@ -265,10 +295,15 @@ fn find_xlib_header(xlib_object: &Nibbles) -> PResult<(u16, u16, u16)> {
 
 /// Decode a library object (without header and size)
 pub(crate) fn next_library(nib: &mut Nibbles) -> PResult<Library> {
-    let lib = nib.clone();
+    let lib = *nib;
     let name = pascal_string(nib)?;
-    let name_len_back = integer2(nib)?;
-    assert_eq!(name_len_back, name.len() as u8);
+    // the length is only repeated for named libraries
+    if !name.is_empty() {
+        let name_len_back = integer2(nib)?;
+        if name_len_back as usize != charset::encode(&name).len() {
+            return malformed(nib);
+        }
+    }
     let number = integer3(nib)?;
 
     // those tables are in arbitrary order
@@ -293,29 +328,33 @@ pub(crate) fn next_library(nib: &mut Nibbles) -> PResult<Library> {
         Vec::new()
     };
     // take message table
-    let message_table = if let Some(nib) = message_table_nibs {
+    let (message_table, linked_messages) = if let Some(nib) = message_table_nibs {
         let mut nib = nib;
         next_message_table(&mut nib)?
     } else {
-        Vec::new()
+        (Vec::new(), false)
     };
     let mut hidden_objects = Vec::new();
     let mut xlib = Vec::new();
     let mut last_obj_location = 0;
+    let mut config_link = None;
     // extract the objects from the link table
-    for offset in link_table.clone() {
+    for (link, offset) in link_table.clone().into_iter().enumerate() {
         if last_obj_location != 0 && last_obj_location + 10 < offset.location() {
             // there are extra objects in between objects from the link table
             // this trick is used for extable, maybe for other objects too
-            let mut nibs = Nibbles::new(&lib[last_obj_location as usize..offset.location()]);
+            let mut nibs = Nibbles::new(&lib[last_obj_location..offset.location()]);
             // on extable, there are 16 nibble of headers (HPHP48..)
-            let _ = take(16usize).parse_next(&mut nibs)?;
+            let header = take(16usize).parse_next(&mut nibs)?;
+            let header = nibbles_to_bytes(&Nibbles::new(header))?;
             if let Ok(obj) =  next_obj(&mut nibs) {
-                extra_objects.push(obj);
+                let padding = nibs.to_vec();
+                extra_objects.push(Hp4xFile { header: header.try_into().unwrap(), obj, padding });
         }
         }
         // skip if config object
         if offset.location() == config_object_nibs.map(|x| x.location()).unwrap_or(0) {
+            config_link = Some(link as u16);
             continue;
         }
         let (kind, library_number, command_number) = find_xlib_header(&offset)?;
@@ -333,7 +372,7 @@ pub(crate) fn next_library(nib: &mut Nibbles) -> PResult<Library> {
                 object: Box::new(obj),
             });
         } else {
-            hidden_objects.push(obj);
+            hidden_objects.push(HiddenObject { command_number: link as u16, object: obj });
         }
     }
     let config_object = if let Some(nib) = config_object_nibs {
@@ -342,16 +381,57 @@ pub(crate) fn next_library(nib: &mut Nibbles) -> PResult<Library> {
     } else {
         None
     };
-    let crc = integer4(nib)?;
+    // the crc is stored in the last 4 nibbles of the library
+    let mut crc_nibs = Nibbles::new(&lib[lib.len().saturating_sub(4)..]);
+    let crc = integer4(&mut crc_nibs)?;
     Ok(Library {
         name,
         number,
         message_table,
+        linked_messages,
         hash_table,
         xlib,
         hidden_objects,
         config_object,
         extra_objects,
+        config_link,
         crc,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn babl49() -> Vec<u8> {
+        let contents = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/BABL49")).unwrap();
+        extract_nibbles(&contents[8..])
+    }
+
+    // a malformed library is a parse error, not a panic
+    #[test]
+    fn test_malformed_library() {
+        assert!(next_obj.parse_next(&mut Nibbles::new(&babl49())).is_ok());
+        // second length of the name
+        let mut nibs = babl49();
+        let name_len = integer2usize(&mut Nibbles::new(&nibs[10..])).unwrap();
+        nibs[12 + 2 * name_len] ^= 1;
+        assert!(next_obj.parse_next(&mut Nibbles::new(&nibs)).is_err());
+        // prolog of the link table
+        let mut nibs = babl49();
+        nibs[292] ^= 1;
+        assert!(next_obj.parse_next(&mut Nibbles::new(&nibs)).is_err());
+    }
+
+    #[test]
+    fn test_xlib_headers() {
+        for (file, number, count) in [("BABL49", 1033, 3), ("extable.HP", 258, 5)] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures").join(file);
+            let Obj::Library(lib) = crate::parse_hp4x(&path).unwrap() else { panic!("{} is not a library", file) };
+            let headers: Vec<_> = lib.xlib.iter().map(|x| (x.kind, x.library_number, x.command_number)).collect();
+            let expected: Vec<_> = (0..count).map(|cmd| (8, number, cmd)).collect();
+            assert_eq!(headers, expected, "{}", file);
+        }
+    }
+}
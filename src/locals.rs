@@ -0,0 +1,330 @@
+//! Local variables
+//!
+//! Locals are bound by `→ a b « … »` in User RPL, compiled as `x->` followed by
+//! the local names and the body, by `FOR i … NEXT`, compiled as `xSTARTVAR`
+//! followed by the local name, and by `{ LAM a LAM b } BIND`, `DOBIND` and
+//! `1LAMBIND` in SysRPL, where `ABND` ends the scope.
+//! The analysis finds the scopes, and reports the locals that are never used
+//! and the references to locals that are not bound.
+use crate::control::Diagnostic;
+use crate::{Extable, Obj};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binder {
+    /// `→ a b « … »`
+    Arrow,
+    /// `FOR i`, up to its `NEXT` or `STEP`
+    For,
+    /// `BIND` or `DOBIND`
+    Bind,
+    /// `1LAMBIND`, a single unnamed local
+    Unnamed,
+}
+
+/// Locals bound together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub binder: Binder,
+    /// `NULLLAM` and unnamed locals are not listed
+    pub names: Vec<String>,
+    /// index of the binding command in its program
+    pub position: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LocalsReport {
+    pub scopes: Vec<Scope>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Names bound by a `→` command, from the tokens following it
+pub(crate) fn arrow_names(tokens: &[Obj]) -> Vec<&str> {
+    tokens
+        .iter()
+        .map_while(|t| match t {
+            Obj::LocalName(n) => Some(n.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Is this the `→` command, named `xRPN->` in the HP49 extable
+pub(crate) fn is_arrow(name: &str) -> bool {
+    matches!(name, "xRPN->" | "x->" | "x→")
+}
+
+/// Is this the `FOR` command, named `xSTARTVAR` in the HP49 extable
+pub(crate) fn is_for(name: &str) -> bool {
+    matches!(name, "xSTARTVAR" | "xFOR")
+}
+
+struct Frame {
+    binder: Binder,
+    names: Vec<(String, bool)>,
+    position: usize,
+}
+
+struct Analyzer<'a> {
+    extable: &'a Extable,
+    frames: Vec<Frame>,
+    report: LocalsReport,
+}
+
+impl Analyzer<'_> {
+    fn name(&self, obj: Option<&Obj>) -> Option<&str> {
+        match obj {
+            Some(Obj::Ext(addr)) => self.extable.addr_to_name.get(addr).map(|n| n.as_str()),
+            _ => None,
+        }
+    }
+
+    fn open(&mut self, binder: Binder, names: Vec<String>, position: usize) {
+        self.report.scopes.push(Scope { binder, names: names.clone(), position });
+        self.frames.push(Frame { binder, names: names.into_iter().map(|n| (n, false)).collect(), position });
+    }
+
+    fn close(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            for (name, used) in frame.names {
+                if !used {
+                    self.report
+                        .diagnostics
+                        .push(Diagnostic { position: frame.position, message: format!("unused local {}", name) });
+                }
+            }
+        }
+    }
+
+    // mark the innermost local of that name as used
+    fn use_local(&mut self, name: &str) -> bool {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some(entry) = frame.names.iter_mut().rev().find(|(n, _)| n == name) {
+                entry.1 = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn unbound(&mut self, position: usize, name: &str) {
+        self.report
+            .diagnostics
+            .push(Diagnostic { position, message: format!("unbound local {}", name) });
+    }
+
+    fn walk(&mut self, obj: &Obj) {
+        match obj {
+            Obj::Prg(v) | Obj::List(v) | Obj::Symb(v) => self.sequence(v),
+            Obj::Tagged(_, o) => self.walk(o),
+            _ => {}
+        }
+    }
+
+    fn sequence(&mut self, tokens: &[Obj]) {
+        let base = self.frames.len();
+        // quoted locals waiting for a DOBIND
+        let mut pending: Vec<(usize, String)> = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let name = self.name(tokens.get(i)).map(str::to_string);
+            match (&tokens[i], name.as_deref()) {
+                (_, Some(n)) if is_arrow(n) => {
+                    let names = arrow_names(&tokens[i + 1..]);
+                    let body = tokens.get(i + 1 + names.len());
+                    self.open(Binder::Arrow, names.iter().map(|n| n.to_string()).collect(), i);
+                    if let Some(body) = body {
+                        self.walk(body);
+                    }
+                    self.close();
+                    i += names.len() + 2;
+                    continue;
+                }
+                (_, Some(n)) if is_for(n) => {
+                    if let Some(Obj::LocalName(variable)) = tokens.get(i + 1) {
+                        self.open(Binder::For, vec![variable.clone()], i);
+                        i += 2;
+                        continue;
+                    }
+                }
+                (_, Some("xNEXT" | "xSTEP")) => {
+                    if self.frames.len() > base && self.frames.last().is_some_and(|f| f.binder == Binder::For) {
+                        self.close();
+                    }
+                }
+                (Obj::List(lams), _) if self.name(tokens.get(i + 1)) == Some("BIND") => {
+                    let names = lams
+                        .iter()
+                        .filter_map(|l| match l {
+                            Obj::LocalName(n) => Some(n.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    self.open(Binder::Bind, names, i + 1);
+                    i += 2;
+                    continue;
+                }
+                (_, Some("DOBIND")) => {
+                    let names = pending.drain(..).map(|(_, n)| n).collect();
+                    self.open(Binder::Bind, names, i);
+                }
+                (_, Some("1LAMBIND")) => self.open(Binder::Unnamed, Vec::new(), i),
+                (_, Some("ABND")) => {
+                    if self.frames.len() > base {
+                        self.close();
+                    }
+                }
+                (_, Some(n)) if n.ends_with("GETLAM") || n.ends_with("PUTLAM") => {
+                    // nGETLAM counts from the last bound local
+                    let index = n.trim_end_matches("GETLAM").trim_end_matches("PUTLAM").parse::<usize>();
+                    if let (Ok(index), Some(frame)) = (index, self.frames.last_mut()) {
+                        let len = frame.names.len();
+                        if index >= 1 && index <= len {
+                            frame.names[len - index].1 = true;
+                        }
+                    }
+                }
+                (_, Some("'")) => {
+                    if let Some(Obj::LocalName(n)) = tokens.get(i + 1) {
+                        if !self.use_local(n) {
+                            pending.push((i + 1, n.clone()));
+                        }
+                        i += 2;
+                        continue;
+                    }
+                }
+                (Obj::LocalName(n), _) => {
+                    if !self.use_local(n) {
+                        self.unbound(i, n);
+                    }
+                }
+                (o, _) => self.walk(o),
+            }
+            i += 1;
+        }
+        for (position, name) in pending {
+            self.unbound(position, &name);
+        }
+        // scopes left open by BIND end with their program
+        while self.frames.len() > base {
+            self.close();
+        }
+    }
+}
+
+/// Scopes, unused and unbound locals of a program and of the programs it contains.
+/// Positions are relative to the program containing the token
+pub fn analyze_locals(obj: &Obj, extable: &Extable) -> LocalsReport {
+    let mut analyzer = Analyzer { extable, frames: Vec::new(), report: LocalsReport::default() };
+    analyzer.walk(obj);
+    analyzer.report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompile::Decompiled;
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        let names = ["x<<", "x>>", "x->", "x+", "BIND", "ABND", "DOBIND", "1LAMBIND", "'", "1GETLAM", "NULLLAM"];
+        for (addr, name) in (1..).zip(names) {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        extable
+    }
+    fn x(name: &str) -> Obj {
+        Obj::Ext(extable().name_to_addr[name])
+    }
+    fn lam(name: &str) -> Obj {
+        Obj::LocalName(name.to_string())
+    }
+
+    // « → a b « a c + » »
+    fn arrow_program() -> Obj {
+        Obj::Prg(vec![
+            x("x<<"),
+            x("x->"),
+            lam("a"),
+            lam("b"),
+            Obj::Prg(vec![x("x<<"), lam("a"), lam("c"), x("x+"), x("x>>")]),
+            x("x>>"),
+        ])
+    }
+
+    #[test]
+    fn test_arrow() {
+        let report = analyze_locals(&arrow_program(), &extable());
+        assert_eq!(
+            report.scopes,
+            vec![Scope { binder: Binder::Arrow, names: vec!["a".to_string(), "b".to_string()], position: 1 }]
+        );
+        let messages: Vec<String> = report.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec!["token 2: unbound local c", "token 1: unused local b"]);
+        assert_eq!(arrow_program().decompile(&extable()), "« → a b « a c + » »");
+    }
+
+    #[test]
+    fn test_sysrpl_bind() {
+        // :: { LAM x NULLLAM } BIND LAM x 1LAMBIND 1GETLAM ABND ABND ;
+        let prg = Obj::Prg(vec![
+            Obj::List(vec![lam("x"), x("NULLLAM")]),
+            x("BIND"),
+            lam("x"),
+            x("1LAMBIND"),
+            x("1GETLAM"),
+            x("ABND"),
+            x("ABND"),
+            lam("x"),
+        ]);
+        let report = analyze_locals(&prg, &extable());
+        assert_eq!(report.scopes.len(), 2);
+        assert_eq!(report.scopes[0].names, vec!["x".to_string()]);
+        assert_eq!(report.scopes[1].binder, Binder::Unnamed);
+        assert_eq!(report.diagnostics, vec![Diagnostic { position: 7, message: "unbound local x".to_string() }]);
+    }
+
+    #[test]
+    fn test_dobind() {
+        // :: ' LAM a ' LAM b DOBIND LAM a ;
+        let prg = Obj::Prg(vec![x("'"), lam("a"), x("'"), lam("b"), x("DOBIND"), lam("a")]);
+        let report = analyze_locals(&prg, &extable());
+        assert_eq!(report.scopes[0].names, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(report.diagnostics, vec![Diagnostic { position: 4, message: "unused local b".to_string() }]);
+    }
+
+    #[test]
+    fn test_for() {
+        // « 1 3 FOR i i NEXT 1 2 FOR j 5 STEP i » with the names of the real extable
+        let extable = crate::extable::tests::fixture();
+        let x = |name: &str| Obj::Ext(extable.name_to_addr[name]);
+        let int = |v: i128| Obj::Int(crate::ZInt::from(v));
+        let prg = Obj::Prg(vec![
+            x("x<<"),
+            int(1),
+            int(3),
+            x("xSTARTVAR"),
+            lam("i"),
+            lam("i"),
+            x("xNEXT"),
+            int(1),
+            int(2),
+            x("xSTARTVAR"),
+            lam("j"),
+            int(5),
+            x("xSTEP"),
+            lam("i"),
+            x("x>>"),
+        ]);
+        let report = analyze_locals(&prg, &extable);
+        assert_eq!(
+            report.scopes,
+            vec![
+                Scope { binder: Binder::For, names: vec!["i".to_string()], position: 3 },
+                Scope { binder: Binder::For, names: vec!["j".to_string()], position: 9 },
+            ]
+        );
+        let messages: Vec<String> = report.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages, vec!["token 9: unused local j", "token 13: unbound local i"]);
+    }
+}
@@ -1,9 +1,15 @@
-use clap;
-use clap::{Parser, Subcommand};
-use rs_hp4x::{parse_hp4x, Extable, Obj};
+use clap::{Parser, Subcommand, ValueEnum};
+use rs_hp4x::{parse_hp4x, write_hp4x, Extable, Grob, Hp4xFile, Monochrome, Obj};
 use anyhow::Result;
 use std::io::Write;
-use rs_hp4x::decompile::Decompiled;
+use rs_hp4x::control::check_structures;
+use rs_hp4x::locals::analyze_locals;
+use rs_hp4x::asm::assemble;
+use rs_hp4x::compile::compile_sysrpl;
+use rs_hp4x::trigraph::write_transfer;
+use rs_hp4x::asc::{from_asc, to_asc};
+use rs_hp4x::transfer::parse_transfer_file;
+use rs_hp4x::decompile::{Decompiled, Dialect};
 
 #[derive(Parser)]
 #[command(name = "rs-hp4x")]
@@ -34,10 +40,73 @@ enum Commands {
         /// The output directory path
         #[arg(short, long)]
         output_dir: String,
+        /// Write the objects as SysRPL source in this syntax
+        #[arg(long, value_enum)]
+        sysrpl: Option<SysRplSyntax>,
+        /// Write the objects as %%HP: text transfers with this translation mode
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=3), conflicts_with = "sysrpl")]
+        translation: Option<u8>,
+    },
+    /// Convert a PNG or PBM image into a GROB transfer file
+    ImportImage {
+        /// The path to the image
+        #[arg(long)]
+        image: String,
+        /// The output file path
+        #[arg(short, long)]
+        output: String,
+        /// Dither gray levels instead of thresholding them
+        #[arg(long)]
+        dither: bool,
+        /// Gray level under which pixels are black
+        #[arg(long, default_value_t = 128)]
+        threshold: u8,
+    },
+    /// Compile SysRPL source into a transfer file
+    Compile {
+        /// The path to the source
+        #[arg(long)]
+        source: String,
+        /// The output file path
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Assemble MASD source into a code object transfer file
+    Assemble {
+        /// The path to the source
+        #[arg(long)]
+        source: String,
+        /// The output file path
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Write an object as an ASC→ string
+    ExportAsc {
+        /// The path to the object
+        #[arg(long)]
+        object: String,
+        /// The output file path
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Convert an ASC→ string into a transfer file
+    ImportAsc {
+        /// The path to the ASC→ string
+        #[arg(long)]
+        asc: String,
+        /// The output file path
+        #[arg(short, long)]
+        output: String,
     },
 }
+#[derive(Clone, Copy, ValueEnum)]
+enum SysRplSyntax {
+    Jazz,
+    Masd,
+}
+
 fn get_extable(path: &str) -> Result<Extable> {
-    let obj = parse_hp4x(&std::path::Path::new(path))?;
+    let obj = parse_hp4x(std::path::Path::new(path))?;
     if let Obj::Library(lib) = obj {
         Ok(Extable::from(lib))
     } else {
@@ -67,27 +136,85 @@ fn main()  -> Result<()> {
                 writeln!(out, "{},0x{:x}", name, addr)?;
             }
         }
-        Commands::DumpObject { object, output_dir } => {
+        Commands::DumpObject { object, output_dir, sysrpl, translation } => {
             println!("Dumping object {} to directory: {}", object, output_dir);
             let extable = extable.unwrap_or_default();
             let in_path = std::path::Path::new(object);
-            let obj = parse_hp4x(in_path)?;
-            fn dump_object(obj: &Obj, output_name: &str, extable: &Extable) -> Result<()> {
+            // text transfers start with a %%HP: header
+            let obj = if std::fs::read(in_path)?.trim_ascii_start().starts_with(b"%%HP:") {
+                parse_transfer_file(in_path, &extable)?
+            } else {
+                parse_hp4x(in_path)?
+            };
+            let dialect = sysrpl.map(|s| match s {
+                SysRplSyntax::Jazz => Dialect::Jazz,
+                SysRplSyntax::Masd => Dialect::Masd,
+            });
+            fn dump_object(
+                obj: &Obj,
+                output_name: &str,
+                extable: &Extable,
+                dialect: Option<Dialect>,
+                translation: Option<u8>,
+            ) -> Result<()> {
                 match obj {
                     Obj::Dir(dir) => {
                         std::fs::create_dir_all(output_name)?;
                         for e in dir.entities.iter() {
-                            dump_object(&e.obj, &format!("{}/{}", output_name, e.name), extable)?;
+                            dump_object(&e.obj, &format!("{}/{}", output_name, e.name), extable, dialect, translation)?;
                         }
                     }
+                    Obj::Grob(grob) => {
+                        std::fs::write(format!("{}.png", output_name), grob.to_png()?)?;
+                    }
                     _ => {
+                        let locals = analyze_locals(obj, extable).diagnostics;
+                        for d in check_structures(obj, extable).into_iter().chain(locals) {
+                            eprintln!("{}: {}", output_name, d);
+                        }
                         let mut out = std::fs::File::create(output_name)?;
-                        out.write_all(obj.decompile(extable).as_bytes())?;
+                        let source = match (dialect, translation) {
+                            (Some(dialect), _) => obj.decompile_sysrpl(extable, dialect).into_bytes(),
+                            (None, Some(translation)) => write_transfer(obj, extable, translation),
+                            (None, None) => obj.decompile(extable).into_bytes(),
+                        };
+                        out.write_all(&source)?;
                     }
                 }
                 Ok(())
             }
-            dump_object(&obj, output_dir, &extable)?;
+            dump_object(&obj, output_dir, &extable, dialect, *translation)?;
+        }
+        Commands::ImportImage { image, output, dither, threshold } => {
+            println!("Converting image {} to GROB file: {}", image, output);
+            let bytes = std::fs::read(image)?;
+            let grob = if bytes.starts_with(b"P1") || bytes.starts_with(b"P4") {
+                Grob::from_pbm(&bytes)?
+            } else {
+                let mode = if *dither { Monochrome::Dither } else { Monochrome::Threshold(*threshold) };
+                Grob::from_png(&bytes, mode)?
+            };
+            write_hp4x(std::path::Path::new(output), &Hp4xFile::new(Obj::Grob(grob), b"HPHP49-C"))?;
+        }
+        Commands::Compile { source, output } => {
+            println!("Compiling {} to file: {}", source, output);
+            let obj = compile_sysrpl(&std::fs::read_to_string(source)?, &extable.unwrap_or_default())?;
+            write_hp4x(std::path::Path::new(output), &Hp4xFile::new(obj, b"HPHP49-C"))?;
+        }
+        Commands::Assemble { source, output } => {
+            println!("Assembling {} to code file: {}", source, output);
+            let code = assemble(&std::fs::read_to_string(source)?, &extable.unwrap_or_default())?;
+            write_hp4x(std::path::Path::new(output), &Hp4xFile::new(code, b"HPHP49-C"))?;
+        }
+        Commands::ExportAsc { object, output } => {
+            println!("Writing {} as an ASC string to file: {}", object, output);
+            let obj = parse_hp4x(std::path::Path::new(object))?;
+            std::fs::write(output, format!("{}\n", to_asc(&obj)))?;
+        }
+        Commands::ImportAsc { asc, output } => {
+            println!("Decoding ASC string {} to file: {}", asc, output);
+            let obj = from_asc(&std::fs::read_to_string(asc)?)?;
+            write_hp4x(std::path::Path::new(output), &Hp4xFile::new(obj, b"HPHP49-C"))?;
         }
 
     }
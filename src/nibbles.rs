@@ -2,10 +2,12 @@
 // base nibble parser utilities, built with winnow
 // basically a re-design of nibblers.rs
 // but using winnow instead of nom
-use winnow::error::{ErrMode, ErrorKind, Needed, ParserError, StrContext};
+use winnow::error::{ErrMode, ErrorKind, ParserError, StrContext};
 use winnow::prelude::*;
 use winnow::stream::{Stream, Located, Location};
 
+use crate::charset;
+
 pub type Nibbles<'a> = Located<&'a[u8]>;
 
 pub fn hexdump_nibbles(nibs: Nibbles, limit: Option<usize>) -> String {
@@ -48,6 +50,7 @@ pub fn hexdump_nibbles(nibs: Nibbles, limit: Option<usize>) -> String {
     }
     s
 }
+#[allow(dead_code)]
 pub fn print_nibbles(nibbles: Nibbles) {
     println!("{}", hexdump_nibbles(nibbles, None));
 }
@@ -81,13 +84,7 @@ macro_rules! integer {
         }
     };
 }
-pub fn decode_bcd(input: &mut Nibbles) -> PResult<u64> {
-    let mut value: u64 = 0;
-    for i in 0..input.len() {
-        value = (value * 10) + (input[i] as u64);
-    }
-    Ok(value)
-}
+integer!(integer15, 15usize, u64);
 integer!(integer12, 12usize, u64);
 integer!(integer5usize, 5usize, usize);
 integer!(integer5, 5usize, u32);
@@ -132,7 +129,7 @@ fn _next_offset<'a>(input: &mut Nibbles<'a>) -> PResult<Option<Nibbles<'a>>> {
         if offset - 5 >= input.len() {
             return Err(ErrMode::Cut(ParserError::from_error_kind(input, ErrorKind::Eof)));
         }
-        let mut input = input.clone();
+        let mut input = *input;
         let _ = input.next_slice(offset - 5);
         Ok(Some(input))
     } else {
@@ -148,7 +145,7 @@ pub fn next_mandatory_offset<'a>(input: &mut Nibbles<'a>) -> PResult<Nibbles<'a>
 }
 /// return a slice of the previous nibbles from the input
 pub fn previous_nibbles<'a>(input: &Nibbles<'a>, count: usize) -> PResult<Nibbles<'a>> {
-    let mut input = input.clone();
+    let mut input = *input;
     let location = input.location();
     input.reset_to_start();
     if location < count {
@@ -157,6 +154,12 @@ pub fn previous_nibbles<'a>(input: &Nibbles<'a>, count: usize) -> PResult<Nibble
     input.next_slice(location - count);
     Ok(input)
 }
+/// pack nibbles into bytes, low nibble first (reverse of extract_nibbles)
+pub fn pack_nibbles(nibs: &[u8]) -> Vec<u8> {
+    nibs.chunks(2)
+        .map(|c| c[0] | (c.get(1).copied().unwrap_or(0) << 4))
+        .collect()
+}
 /// Convert the whole input to u8
 pub fn nibbles_to_bytes(input: &Nibbles) -> PResult<Vec<u8>> {
     let len = input.len() / 2;
@@ -169,6 +172,11 @@ pub fn nibbles_to_bytes(input: &Nibbles) -> PResult<Vec<u8>> {
     }
     Ok(bytes)
 }
+/// CRC of a nibble stream, as computed by the hardware CRC module of the saturn
+/// (x^16 + x^12 + x^5 + 1, fed one nibble at a time)
+pub fn hp_crc(nibs: &[u8]) -> u16 {
+    nibs.iter().fold(0u16, |crc, &n| (crc >> 4) ^ (((crc ^ n as u16) & 0xf) * 0x1081))
+}
 /// parse a pascal string
 pub fn pascal_string(input: &mut Nibbles) -> PResult<String> {
     _pascal_string.context(StrContext::Label("pascal string")).parse_next(input)
@@ -180,10 +188,7 @@ fn _pascal_string(input: &mut Nibbles) -> PResult<String> {
     }
     let str = input.next_slice(len);
     let bytes = nibbles_to_bytes(&Nibbles::new(str))?;
-    // TODO: create an actual codec for hp4x charset. 
-    //would be useful to convert e.g for the -> char
-    let s = String::from_utf8_lossy(&bytes).to_string();
-    Ok(s)
+    Ok(charset::decode(&bytes))
 }
 pub fn next_tlv<'a>(input: &mut Nibbles<'a>) -> PResult<(u32, Nibbles<'a>)> {
     _next_tlv.context(StrContext::Label("tlv")).parse_next(input)
@@ -214,7 +219,143 @@ fn _next_lv<'a>(input: &mut Nibbles<'a>) -> PResult<Nibbles<'a>> {
     Ok(Located::new(value))
 }
 
+/// Writer counterpart of the nibble parsers above.
+/// Nibbles are accumulated in memory, fields that depend on what follows
+/// (sizes and offsets) are reserved first, then back-patched.
+#[derive(Debug, Default, Clone)]
+pub struct NibbleWriter {
+    nibs: Vec<u8>,
+}
+/// a reserved 5 nibbles size field, see `NibbleWriter::reserve_size`
+#[derive(Debug, Clone, Copy)]
+pub struct SizeField(usize);
+/// a reserved 5 nibbles offset field, see `NibbleWriter::reserve_offset`
+#[derive(Debug, Clone, Copy)]
+pub struct OffsetField(usize);
+impl OffsetField {
+    pub fn position(&self) -> usize {
+        self.0
+    }
+}
+impl SizeField {
+    pub fn position(&self) -> usize {
+        self.0
+    }
+}
+
+macro_rules! put_integer {
+    ($name:ident, $count:expr, $input:ty) => {
+        #[allow(dead_code)]
+        pub fn $name(&mut self, value: $input) {
+            self.put_integer(value as u64, $count);
+        }
+    };
+}
+impl NibbleWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// current position, in nibbles
+    pub fn len(&self) -> usize {
+        self.nibs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nibs.is_empty()
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.nibs
+    }
+    pub fn into_nibbles(self) -> Vec<u8> {
+        self.nibs
+    }
+    /// pack the nibbles into bytes, the last byte is padded with a zero nibble if needed
+    pub fn to_bytes(&self) -> Vec<u8> {
+        pack_nibbles(&self.nibs)
+    }
+    pub fn put_nibble(&mut self, nib: u8) {
+        self.nibs.push(nib & 0xf);
+    }
+    pub fn put_nibbles(&mut self, nibs: &[u8]) {
+        self.nibs.extend(nibs.iter().map(|n| n & 0xf));
+    }
+    /// put `count` nibbles of `value`, least significant nibble first
+    pub fn put_integer(&mut self, value: u64, count: usize) {
+        for i in 0..count {
+            self.nibs.push(((value >> (4 * i)) & 0xf) as u8);
+        }
+    }
+    /// overwrite `count` nibbles at `pos` with `value`
+    pub fn patch_integer(&mut self, pos: usize, value: u64, count: usize) {
+        for i in 0..count {
+            self.nibs[pos + i] = ((value >> (4 * i)) & 0xf) as u8;
+        }
+    }
+    put_integer!(put_integer15, 15, u64);
+    put_integer!(put_integer12, 12, u64);
+    put_integer!(put_integer5usize, 5, usize);
+    put_integer!(put_integer5, 5, u32);
+    put_integer!(put_integer4, 4, u16);
+    put_integer!(put_integer3, 3, u16);
+    put_integer!(put_integer2usize, 2, usize);
+    put_integer!(put_integer2, 2, u8);
+    put_integer!(put_integer1, 1, u8);
+    /// put bytes, low nibble first (reverse of nibbles_to_bytes)
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.nibs.push(b & 0xf);
+            self.nibs.push(b >> 4);
+        }
+    }
+    /// put a pascal string (reverse of pascal_string)
+    pub fn put_pascal_string(&mut self, s: &str) {
+        let bytes = charset::encode(s);
+        self.put_integer2usize(bytes.len());
+        self.put_bytes(&bytes);
+    }
+    /// reserve a size field, to be patched by `patch_size` once the content is written
+    pub fn reserve_size(&mut self) -> SizeField {
+        let pos = self.len();
+        self.put_integer5(0);
+        SizeField(pos)
+    }
+    /// sizes count their own 5 nibbles, and everything written after them
+    pub fn patch_size(&mut self, field: SizeField) {
+        let size = self.len() - field.0;
+        self.patch_integer(field.0, size as u64, 5);
+    }
+    /// reserve an offset field, to be patched by `patch_offset` when its target is written
+    pub fn reserve_offset(&mut self) -> OffsetField {
+        let pos = self.len();
+        self.put_integer5(0);
+        OffsetField(pos)
+    }
+    /// point the offset field to the current position
+    pub fn patch_offset(&mut self, field: OffsetField) {
+        self.patch_offset_to(field, self.len());
+    }
+    /// point the offset field to `target`. offsets are relative to the offset field itself
+    pub fn patch_offset_to(&mut self, field: OffsetField, target: usize) {
+        self.patch_integer(field.0, (target - field.0) as u64, 5);
+    }
+    /// length-prefixed block (reverse of next_lv), the length counts itself
+    pub fn put_lv(&mut self, content: impl FnOnce(&mut Self)) {
+        let size = self.reserve_size();
+        content(self);
+        self.patch_size(size);
+    }
+    /// tag, length and value block (reverse of next_tlv), the length does not count itself
+    pub fn put_tlv(&mut self, tag: u32, content: impl FnOnce(&mut Self)) {
+        self.put_integer5(tag);
+        let size = self.reserve_size();
+        content(self);
+        let length = self.len() - size.0 - 5;
+        self.patch_integer(size.0, length as u64, 5);
+    }
+}
+
+
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
 
@@ -226,6 +367,13 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_pack_nibbles() {
+        let input = vec![0x12, 0x34, 0x56, 0x78];
+        assert_eq!(pack_nibbles(&extract_nibbles(&input)), input);
+        assert_eq!(pack_nibbles(&[0x1, 0x2, 0x3]), vec![0x21, 0x03]);
+    }
+
     #[test]
     fn test_integer5() {
         let input = vec![0x1, 0x2, 0x3, 0x4, 0x5];
@@ -260,6 +408,12 @@ mod tests {
         assert_eq!(err.to_string(), "\u{10}\0\0\0\0\u{2}\u{3}\u{4}\u{5}\n     ^\ninvalid offset");
     }
     #[test]
+    fn test_hp_crc() {
+        assert_eq!(hp_crc(&[]), 0);
+        assert_eq!(hp_crc(&[0x1]), 0x1081);
+        assert_eq!(hp_crc(&[0x1, 0x2]), 0x308b);
+    }
+    #[test]
     fn test_pascal_string() {
         let input = vec![0x3, 0x0, 0x1, 0x6, 0x2, 0x6, 0x3, 0x6];
         let mut nibbles: Nibbles = Nibbles::new(&input);
@@ -275,6 +429,52 @@ mod tests {
         assert_eq!(format!("{:}", err), "\u{3}\0\u{1}\u{6}\u{2}\u{6}\u{3}\n  ^\ninvalid pascal string");
     }
 
+    #[test]
+    fn test_writer_integers() {
+        let mut w = NibbleWriter::new();
+        w.put_integer5(0x54321);
+        w.put_integer2(0x21);
+        w.put_integer3(0x543);
+        assert_eq!(w.as_slice(), &[0x1, 0x2, 0x3, 0x4, 0x5, 0x1, 0x2, 0x3, 0x4, 0x5]);
+        let mut nibbles = Nibbles::new(w.as_slice());
+        assert_eq!(integer5(&mut nibbles).unwrap(), 0x54321);
+        assert_eq!(integer2(&mut nibbles).unwrap(), 0x21);
+        assert_eq!(integer3(&mut nibbles).unwrap(), 0x543);
+    }
+    #[test]
+    fn test_writer_pascal_string() {
+        let mut w = NibbleWriter::new();
+        w.put_pascal_string("abc");
+        assert_eq!(w.as_slice(), &[0x3, 0x0, 0x1, 0x6, 0x2, 0x6, 0x3, 0x6]);
+        assert_eq!(w.to_bytes(), vec![0x03, 0x61, 0x62, 0x63]);
+    }
+    #[test]
+    fn test_writer_lv_tlv() {
+        let mut w = NibbleWriter::new();
+        w.put_lv(|w| w.put_nibbles(&[0x1, 0x2, 0x3]));
+        w.put_tlv(0x02A4E, |w| w.put_nibbles(&[0x4, 0x5]));
+        let mut nibbles = Nibbles::new(w.as_slice());
+        assert_eq!(*next_lv(&mut nibbles).unwrap(), &[0x1, 0x2, 0x3]);
+        let (tag, value) = next_tlv(&mut nibbles).unwrap();
+        assert_eq!(tag, 0x02A4E);
+        assert_eq!(*value, &[0x4, 0x5]);
+        assert!(nibbles.is_empty());
+    }
+    #[test]
+    fn test_writer_offsets() {
+        let mut w = NibbleWriter::new();
+        let empty = w.reserve_offset();
+        let forward = w.reserve_offset();
+        w.put_nibbles(&[0, 0, 0]);
+        w.patch_offset(forward);
+        w.put_integer5(0x12345);
+        assert_eq!(empty.position(), 0);
+        let mut nibbles = Nibbles::new(w.as_slice());
+        assert!(next_offset(&mut nibbles).unwrap().is_none());
+        let mut target = next_mandatory_offset(&mut nibbles).unwrap();
+        assert_eq!(integer5(&mut target).unwrap(), 0x12345);
+    }
+
     #[test]
     fn test_hexdump_nibbles() {
         let input = vec![0x2, 0x6, 0xf, 0x6, 0xe, 0x6, 0xa, 0x6, 0xf, 0x6, 0x5, 0x7, 0x2, 0x7, 0x1,0x2];
@@ -0,0 +1,404 @@
+//! LaTeX and MathML rendering of algebraic expressions
+//!
+//! Divisions are rendered as fractions, so their operands need no parentheses,
+//! and `√`, `XROOT`, `∫`, `∂` and `Σ` use the usual math notations.
+use crate::symbolic::PRODUCT;
+use crate::{Expr, Extable, Fixity, Obj, UnitExpr};
+
+impl Obj {
+    /// LaTeX form of a symbolic, None for other objects or unknown operators
+    pub fn to_latex(&self, extable: &Extable) -> Option<String> {
+        self.to_expr(extable).map(|e| latex(&e))
+    }
+    /// MathML form of a symbolic, None for other objects or unknown operators
+    pub fn to_mathml(&self, extable: &Extable) -> Option<String> {
+        self.to_expr(extable)
+            .map(|e| format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>", mathml(&e)))
+    }
+    fn to_expr(&self, extable: &Extable) -> Option<Expr> {
+        match self {
+            Obj::Symb(tokens) => Expr::from_tokens(tokens, extable),
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    pub fn to_latex(&self) -> String {
+        latex(self)
+    }
+    pub fn to_mathml(&self) -> String {
+        mathml(self)
+    }
+}
+
+// greek letters and symbols of the calculator charset
+const LATEX_SYMBOLS: &[(char, &str)] = &[
+    ('π', "\\pi"),
+    ('α', "\\alpha"),
+    ('γ', "\\gamma"),
+    ('δ', "\\delta"),
+    ('ε', "\\varepsilon"),
+    ('η', "\\eta"),
+    ('θ', "\\theta"),
+    ('λ', "\\lambda"),
+    ('ρ', "\\rho"),
+    ('σ', "\\sigma"),
+    ('τ', "\\tau"),
+    ('ω', "\\omega"),
+    ('Δ', "\\Delta"),
+    ('Π', "\\Pi"),
+    ('Σ', "\\Sigma"),
+    ('Ω', "\\Omega"),
+    ('∞', "\\infty"),
+];
+
+// functions that have a LaTeX command
+const LATEX_FUNCTIONS: &[(&str, &str)] = &[
+    ("xSIN", "\\sin"),
+    ("xCOS", "\\cos"),
+    ("xTAN", "\\tan"),
+    ("xASIN", "\\arcsin"),
+    ("xACOS", "\\arccos"),
+    ("xATAN", "\\arctan"),
+    ("xSINH", "\\sinh"),
+    ("xCOSH", "\\cosh"),
+    ("xTANH", "\\tanh"),
+    ("xLN", "\\ln"),
+    ("xLOG", "\\log"),
+    ("xMIN", "\\min"),
+    ("xMAX", "\\max"),
+];
+
+// "2." is 2, ".5" is 0.5 and "1.5E-3" is 1.5·10^-3
+fn number_parts(n: &str) -> (String, Option<&str>) {
+    let (mantissa, exponent) = match n.split_once('E') {
+        Some((m, e)) => (m, Some(e)),
+        None => (n, None),
+    };
+    let mut m = mantissa.trim_end_matches('.').to_string();
+    if m.starts_with('.') || m.starts_with("-.") {
+        m = m.replacen('.', "0.", 1);
+    }
+    if m.is_empty() || m == "-" {
+        m.push('0');
+    }
+    (m, exponent)
+}
+
+// characters with a meaning in LaTeX, in text and math mode alike
+fn latex_escape(c: char, s: &mut String) {
+    match c {
+        '\\' => s.push_str("\\textbackslash{}"),
+        '_' | '&' | '%' | '#' | '$' | '{' | '}' => {
+            s.push('\\');
+            s.push(c);
+        }
+        _ => s.push(c),
+    }
+}
+
+fn latex_text(text: &str) -> String {
+    let mut s = String::new();
+    for c in text.chars() {
+        latex_escape(c, &mut s);
+    }
+    format!("\\text{{{}}}", s)
+}
+
+fn latex_name(name: &str) -> String {
+    let mut s = String::new();
+    for c in name.chars() {
+        match LATEX_SYMBOLS.iter().find(|(u, _)| *u == c) {
+            Some((_, l)) => {
+                s.push_str(l);
+                s.push(' ');
+            }
+            None => latex_escape(c, &mut s),
+        }
+    }
+    let s = s.trim_end().to_string();
+    let symbol = name.starts_with(|c| LATEX_SYMBOLS.iter().any(|(u, _)| *u == c));
+    if name.chars().count() > 1 && !symbol {
+        format!("\\mathrm{{{}}}", s)
+    } else {
+        s
+    }
+}
+
+fn latex_unit(unit: &UnitExpr) -> String {
+    match unit {
+        UnitExpr::Name(n) => format!("\\mathrm{{{}}}", n),
+        UnitExpr::Mul(a, b) => format!("{}\\cdot {}", latex_unit(a), latex_unit(b)),
+        UnitExpr::Div(a, b) => format!("\\frac{{{}}}{{{}}}", latex_unit(a), latex_unit(b)),
+        UnitExpr::Pow(a, e) => match **a {
+            UnitExpr::Name(_) => format!("{}^{{{}}}", latex_unit(a), e),
+            _ => format!("\\left({}\\right)^{{{}}}", latex_unit(a), e),
+        },
+    }
+}
+
+fn latex_operand(e: &Expr, min_precedence: u8) -> String {
+    if e.precedence() < min_precedence {
+        format!("\\left({}\\right)", latex(e))
+    } else {
+        latex(e)
+    }
+}
+
+fn latex_args(args: &[Expr]) -> String {
+    args.iter().map(latex).collect::<Vec<_>>().join(",")
+}
+
+fn latex(e: &Expr) -> String {
+    match e {
+        Expr::Number(n) => match number_parts(n) {
+            (m, Some(exp)) => format!("{}\\times 10^{{{}}}", m, exp),
+            (m, None) => m,
+        },
+        Expr::Name(n) => latex_name(n),
+        Expr::Atom(a) => latex_text(a),
+        Expr::Unit(value, unit) => format!("{}\\,{}", latex(&Expr::Number(value.clone())), latex_unit(unit)),
+        Expr::Call(name, args) => format!("{}\\left({}\\right)", latex_name(name), latex_args(args)),
+        Expr::Apply(op, args) => {
+            let p = op.precedence;
+            match (op.fixity, op.name) {
+                (_, "x/") => format!("\\frac{{{}}}{{{}}}", latex(&args[0]), latex(&args[1])),
+                (_, "x^") => format!("{}^{{{}}}", latex_operand(&args[0], p + 1), latex(&args[1])),
+                (_, "xSQRT") => format!("\\sqrt{{{}}}", latex(&args[0])),
+                (_, "xXROOT") => format!("\\sqrt[{}]{{{}}}", latex(&args[1]), latex(&args[0])),
+                (_, "xINV") => format!("\\frac{{1}}{{{}}}", latex(&args[0])),
+                (_, "xSQ") => format!("{}^{{2}}", latex_operand(&args[0], p)),
+                (_, "xEXP") => format!("e^{{{}}}", latex(&args[0])),
+                (_, "xABS") => format!("\\left|{}\\right|", latex(&args[0])),
+                (_, "xDER") => format!(
+                    "\\frac{{\\partial}}{{\\partial {}}}\\left({}\\right)",
+                    latex(&args[0]),
+                    latex(&args[1])
+                ),
+                (_, "xINTEGRAL") => format!(
+                    "\\int_{{{}}}^{{{}}} {}\\,\\mathrm{{d}}{}",
+                    latex(&args[0]),
+                    latex(&args[1]),
+                    latex(&args[2]),
+                    latex(&args[3])
+                ),
+                (_, "xSUM") => format!(
+                    "\\sum_{{{}={}}}^{{{}}} {}",
+                    latex(&args[0]),
+                    latex(&args[1]),
+                    latex(&args[2]),
+                    latex_operand(&args[3], PRODUCT)
+                ),
+                (Fixity::Infix | Fixity::InfixRight, _) => {
+                    let symbol = match op.name {
+                        "x*" => "\\cdot ",
+                        "x<=?" => "\\le ",
+                        "x>=?" => "\\ge ",
+                        "x#?" => "\\ne ",
+                        "xAND" => "\\land ",
+                        "xOR" => "\\lor ",
+                        "xXOR" => "\\oplus ",
+                        "xWHERE" => "\\mid ",
+                        _ => op.symbol,
+                    };
+                    format!("{}{}{}", latex_operand(&args[0], p), symbol, latex_operand(&args[1], p + 1))
+                }
+                (Fixity::Prefix, _) => {
+                    let symbol = if op.name == "xNOT" { "\\lnot " } else { op.symbol };
+                    format!("{}{}", symbol, latex_operand(&args[0], p))
+                }
+                (Fixity::Postfix, _) => format!("{}{}", latex_operand(&args[0], p), op.symbol),
+                (Fixity::Constant, _) => latex_name(op.symbol),
+                (Fixity::Function, _) => {
+                    let f = LATEX_FUNCTIONS
+                        .iter()
+                        .find(|(n, _)| *n == op.name)
+                        .map(|(_, l)| l.to_string())
+                        .unwrap_or_else(|| format!("\\operatorname{{{}}}", op.symbol));
+                    format!("{}\\left({}\\right)", f, latex_args(args))
+                }
+            }
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn mo(s: &str) -> String {
+    format!("<mo>{}</mo>", escape(s.trim()))
+}
+
+fn mrow(items: &[String]) -> String {
+    format!("<mrow>{}</mrow>", items.concat())
+}
+
+fn mathml_name(name: &str) -> String {
+    format!("<mi>{}</mi>", escape(name))
+}
+
+fn mathml_unit(unit: &UnitExpr) -> String {
+    match unit {
+        UnitExpr::Name(n) => format!("<mi mathvariant=\"normal\">{}</mi>", escape(n)),
+        UnitExpr::Mul(a, b) => mrow(&[mathml_unit(a), mo("⋅"), mathml_unit(b)]),
+        UnitExpr::Div(a, b) => format!("<mfrac>{}{}</mfrac>", mathml_unit(a), mathml_unit(b)),
+        UnitExpr::Pow(a, e) => {
+            let base = match **a {
+                UnitExpr::Name(_) => mathml_unit(a),
+                _ => mrow(&[mo("("), mathml_unit(a), mo(")")]),
+            };
+            format!("<msup>{}<mn>{}</mn></msup>", base, escape(e))
+        }
+    }
+}
+
+fn mathml_number(n: &str) -> String {
+    match number_parts(n) {
+        (m, Some(exp)) => mrow(&[
+            format!("<mn>{}</mn>", m),
+            mo("×"),
+            format!("<msup><mn>10</mn><mn>{}</mn></msup>", exp),
+        ]),
+        (m, None) => format!("<mn>{}</mn>", m),
+    }
+}
+
+fn mathml_operand(e: &Expr, min_precedence: u8) -> String {
+    if e.precedence() < min_precedence {
+        mrow(&[mo("("), mathml(e), mo(")")])
+    } else {
+        mathml(e)
+    }
+}
+
+fn mathml_call(f: String, args: &[Expr]) -> String {
+    let mut items = vec![f, mo("(")];
+    for (i, a) in args.iter().enumerate() {
+        if i > 0 {
+            items.push(mo(","));
+        }
+        items.push(mathml(a));
+    }
+    items.push(mo(")"));
+    mrow(&items)
+}
+
+fn mathml(e: &Expr) -> String {
+    match e {
+        Expr::Number(n) => mathml_number(n),
+        Expr::Name(n) => mathml_name(n),
+        Expr::Atom(a) => format!("<mtext>{}</mtext>", escape(a)),
+        Expr::Unit(value, unit) => mrow(&[mathml_number(value), "<mspace width=\"0.2em\"/>".to_string(), mathml_unit(unit)]),
+        Expr::Call(name, args) => mathml_call(mathml_name(name), args),
+        Expr::Apply(op, args) => {
+            let p = op.precedence;
+            match (op.fixity, op.name) {
+                (_, "x/") => format!("<mfrac>{}{}</mfrac>", mathml(&args[0]), mathml(&args[1])),
+                (_, "x^") => format!("<msup>{}{}</msup>", mathml_operand(&args[0], p + 1), mathml(&args[1])),
+                (_, "xSQRT") => format!("<msqrt>{}</msqrt>", mathml(&args[0])),
+                (_, "xXROOT") => format!("<mroot>{}{}</mroot>", mathml(&args[0]), mathml(&args[1])),
+                (_, "xINV") => format!("<mfrac><mn>1</mn>{}</mfrac>", mathml(&args[0])),
+                (_, "xSQ") => format!("<msup>{}<mn>2</mn></msup>", mathml_operand(&args[0], p)),
+                (_, "xEXP") => format!("<msup><mi>e</mi>{}</msup>", mathml(&args[0])),
+                (_, "xABS") => mrow(&[mo("|"), mathml(&args[0]), mo("|")]),
+                (_, "xDER") => mrow(&[
+                    format!("<mfrac><mo>∂</mo><mrow><mo>∂</mo>{}</mrow></mfrac>", mathml(&args[0])),
+                    mo("("),
+                    mathml(&args[1]),
+                    mo(")"),
+                ]),
+                (_, "xINTEGRAL") => mrow(&[
+                    format!("<msubsup><mo>∫</mo>{}{}</msubsup>", mathml(&args[0]), mathml(&args[1])),
+                    mathml(&args[2]),
+                    "<mspace width=\"0.2em\"/>".to_string(),
+                    "<mi mathvariant=\"normal\">d</mi>".to_string(),
+                    mathml(&args[3]),
+                ]),
+                (_, "xSUM") => mrow(&[
+                    format!(
+                        "<munderover><mo>∑</mo>{}{}</munderover>",
+                        mrow(&[mathml(&args[0]), mo("="), mathml(&args[1])]),
+                        mathml(&args[2])
+                    ),
+                    mathml_operand(&args[3], PRODUCT),
+                ]),
+                (Fixity::Infix | Fixity::InfixRight, _) => {
+                    let symbol = if op.name == "x*" { "⋅" } else { op.symbol };
+                    mrow(&[mathml_operand(&args[0], p), mo(symbol), mathml_operand(&args[1], p + 1)])
+                }
+                (Fixity::Prefix, _) => {
+                    let symbol = if op.name == "xNOT" { "¬" } else { op.symbol };
+                    mrow(&[mo(symbol), mathml_operand(&args[0], p)])
+                }
+                (Fixity::Postfix, _) => mrow(&[mathml_operand(&args[0], p), mo(op.symbol)]),
+                (Fixity::Constant, _) => mathml_name(op.symbol),
+                (Fixity::Function, _) => mathml_call(mathml_name(op.symbol), args),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbolic::tests::{extable, id, real, x};
+    use crate::{Expr, Obj};
+
+    fn expr(tokens: Vec<Obj>) -> Expr {
+        Expr::from_tokens(&tokens, &extable()).unwrap()
+    }
+
+    #[test]
+    fn test_latex() {
+        // (X+1)/2 = √X
+        let e = expr(vec![id("X"), real(1.0), x("x+"), real(2.0), x("x/"), id("X"), x("xSQRT"), x("x=")]);
+        assert_eq!(e.to_latex(), "\\frac{X+1}{2}=\\sqrt{X}");
+        // SIN(θ)^2*(A-B)
+        let e = expr(vec![id("θ"), x("xSIN"), real(2.0), x("x^"), id("A"), id("B"), x("x-"), x("x*")]);
+        assert_eq!(e.to_latex(), "\\sin\\left(\\theta\\right)^{2}\\cdot \\left(A-B\\right)");
+        // ∫(0,1,X^2,X), ∂X(F(X)), XROOT(8,3)
+        let e = expr(vec![real(0.0), real(1.0), id("X"), real(2.0), x("x^"), id("X"), x("xINTEGRAL")]);
+        assert_eq!(e.to_latex(), "\\int_{0}^{1} X^{2}\\,\\mathrm{d}X");
+        let e = expr(vec![id("X"), id("X"), Obj::Bint(1), id("F"), x("xFCNAPPLY"), x("xDER")]);
+        assert_eq!(e.to_latex(), "\\frac{\\partial}{\\partial X}\\left(F\\left(X\\right)\\right)");
+        let e = expr(vec![real(8.0), real(3.0), x("xXROOT"), real(0.5), x("x<=?")]);
+        assert_eq!(e.to_latex(), "\\sqrt[3]{8}\\le 0.5");
+    }
+
+    #[test]
+    fn test_latex_escapes() {
+        assert_eq!(Expr::Name("A_B".to_string()).to_latex(), "\\mathrm{A\\_B}");
+        assert_eq!(Expr::Name("_".to_string()).to_latex(), "\\_");
+        let e = Expr::Atom("\"50% & #1_\"".to_string());
+        assert_eq!(e.to_latex(), "\\text{\"50\\% \\& \\#1\\_\"}");
+    }
+
+    #[test]
+    fn test_latex_units() {
+        let e = Expr::Unit(
+            "9.81".to_string(),
+            crate::UnitExpr::Div(
+                Box::new(crate::UnitExpr::Name("m".to_string())),
+                Box::new(crate::UnitExpr::Pow(Box::new(crate::UnitExpr::Name("s".to_string())), "2".to_string())),
+            ),
+        );
+        assert_eq!(e.to_latex(), "9.81\\,\\frac{\\mathrm{m}}{\\mathrm{s}^{2}}");
+        assert_eq!(
+            e.to_mathml(),
+            "<mrow><mn>9.81</mn><mspace width=\"0.2em\"/><mfrac><mi mathvariant=\"normal\">m</mi>\
+             <msup><mi mathvariant=\"normal\">s</mi><mn>2</mn></msup></mfrac></mrow>"
+        );
+    }
+
+    #[test]
+    fn test_mathml() {
+        let symb = Obj::Symb(vec![id("A"), real(1.0), x("x<"), id("X"), x("xSQRT"), x("xINV"), x("xAND")]);
+        assert_eq!(
+            symb.to_mathml(&extable()).unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><mrow><mi>A</mi><mo>&lt;</mo><mn>1</mn></mrow>\
+             <mo>AND</mo><mfrac><mn>1</mn><msqrt><mi>X</mi></msqrt></mfrac></mrow></math>"
+        );
+        assert!(Obj::Real(crate::Real::from_float(1.0)).to_mathml(&extable()).is_none());
+    }
+}
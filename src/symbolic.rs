@@ -0,0 +1,384 @@
+//! Algebraic objects
+//!
+//! Symbolic objects are stored in RPN: `'SIN(X)^2+1'` is `X xSIN 2 x^ 1 x+`.
+//! The built-in operators are recognized by their extable names, then the
+//! expression is rendered in infix with the fewest parentheses.
+//! User functions are stored as their arguments, the argument count,
+//! the function name and `xFCNAPPLY`.
+use std::fmt;
+
+use crate::decompile::Decompiled;
+use crate::{Extable, Obj, Unit, UnitExpr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fixity {
+    /// left associative binary operator, e.g. `A-B`
+    Infix,
+    /// right associative binary operator, e.g. `A^B`
+    InfixRight,
+    /// e.g. `-A`
+    Prefix,
+    /// e.g. `A!`
+    Postfix,
+    /// e.g. `SIN(A)`
+    Function,
+    /// e.g. `π`
+    Constant,
+}
+
+/// Built-in operator of algebraic expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operator {
+    /// extable name, e.g. `x+`
+    pub name: &'static str,
+    /// as displayed, e.g. `+`
+    pub symbol: &'static str,
+    pub arity: usize,
+    pub fixity: Fixity,
+    /// higher binds tighter
+    pub precedence: u8,
+}
+
+// precedence of atoms and function calls
+pub(crate) const ATOM: u8 = 12;
+pub(crate) const PRODUCT: u8 = 7;
+const NEGATION: u8 = 8;
+
+const fn op(name: &'static str, symbol: &'static str, arity: usize, fixity: Fixity, precedence: u8) -> Operator {
+    Operator { name, symbol, arity, fixity, precedence }
+}
+const fn func(name: &'static str, symbol: &'static str, arity: usize) -> Operator {
+    op(name, symbol, arity, Fixity::Function, ATOM)
+}
+const fn constant(name: &'static str, symbol: &'static str) -> Operator {
+    op(name, symbol, 0, Fixity::Constant, ATOM)
+}
+
+pub const OPERATORS: &[Operator] = &[
+    op("xWHERE", "|", 2, Fixity::Infix, 0),
+    op("x=", "=", 2, Fixity::Infix, 1),
+    op("x==", "==", 2, Fixity::Infix, 5),
+    op("x<", "<", 2, Fixity::Infix, 5),
+    op("x>", ">", 2, Fixity::Infix, 5),
+    op("x<=?", "≤", 2, Fixity::Infix, 5),
+    op("x>=?", "≥", 2, Fixity::Infix, 5),
+    op("x#?", "≠", 2, Fixity::Infix, 5),
+    op("xOR", " OR ", 2, Fixity::Infix, 2),
+    op("xXOR", " XOR ", 2, Fixity::Infix, 2),
+    op("xAND", " AND ", 2, Fixity::Infix, 3),
+    op("xNOT", "NOT ", 1, Fixity::Prefix, 4),
+    op("x+", "+", 2, Fixity::Infix, 6),
+    op("x-", "-", 2, Fixity::Infix, 6),
+    op("x*", "*", 2, Fixity::Infix, PRODUCT),
+    op("x/", "/", 2, Fixity::Infix, PRODUCT),
+    op("xNEG", "-", 1, Fixity::Prefix, NEGATION),
+    op("x^", "^", 2, Fixity::InfixRight, 9),
+    op("xSQRT", "√", 1, Fixity::Prefix, 10),
+    op("xFACT", "!", 1, Fixity::Postfix, ATOM),
+    func("xSIN", "SIN", 1),
+    func("xCOS", "COS", 1),
+    func("xTAN", "TAN", 1),
+    func("xASIN", "ASIN", 1),
+    func("xACOS", "ACOS", 1),
+    func("xATAN", "ATAN", 1),
+    func("xSINH", "SINH", 1),
+    func("xCOSH", "COSH", 1),
+    func("xTANH", "TANH", 1),
+    func("xASINH", "ASINH", 1),
+    func("xACOSH", "ACOSH", 1),
+    func("xATANH", "ATANH", 1),
+    func("xEXP", "EXP", 1),
+    func("xEXPM", "EXPM", 1),
+    func("xLN", "LN", 1),
+    func("xLNP1", "LNP1", 1),
+    func("xLOG", "LOG", 1),
+    func("xALOG", "ALOG", 1),
+    func("xSQ", "SQ", 1),
+    func("xABS", "ABS", 1),
+    func("xINV", "INV", 1),
+    func("xSIGN", "SIGN", 1),
+    func("xIP", "IP", 1),
+    func("xFP", "FP", 1),
+    func("xFLOOR", "FLOOR", 1),
+    func("xCEIL", "CEIL", 1),
+    func("xARG", "ARG", 1),
+    func("xRE", "RE", 1),
+    func("xIM", "IM", 1),
+    func("xCONJ", "CONJ", 1),
+    func("xMANT", "MANT", 1),
+    func("xXPON", "XPON", 1),
+    func("xR>D", "R→D", 1),
+    func("xD>R", "D→R", 1),
+    func("xXROOT", "XROOT", 2),
+    func("x%", "%", 2),
+    func("x%T", "%T", 2),
+    func("x%CH", "%CH", 2),
+    func("xMOD", "MOD", 2),
+    func("xMIN", "MIN", 2),
+    func("xMAX", "MAX", 2),
+    func("xRND", "RND", 2),
+    func("xTRNC", "TRNC", 2),
+    func("xCOMB", "COMB", 2),
+    func("xPERM", "PERM", 2),
+    func("xIFTE", "IFTE", 3),
+    // ∂X(F), stored as X F xDER
+    func("xDER", "∂", 2),
+    // ∫(A,B,F,X)
+    func("xINTEGRAL", "∫", 4),
+    // Σ(I=A,B,F), stored as I A B F xSUM
+    func("xSUM", "Σ", 4),
+    constant("xPI", "π"),
+    constant("xi", "i"),
+    constant("xCONSTANTe", "e"),
+    constant("xMAXR", "MAXR"),
+    constant("xMINR", "MINR"),
+];
+
+impl Operator {
+    /// built-in operator from its extable name
+    pub fn from_name(name: &str) -> Option<&'static Operator> {
+        OPERATORS.iter().find(|o| o.name == name)
+    }
+}
+
+/// Algebraic expression tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(String),
+    /// global or local name
+    Name(String),
+    /// unit object, value and unit
+    Unit(String, UnitExpr),
+    /// any other object, as decompiled
+    Atom(String),
+    Apply(&'static Operator, Vec<Expr>),
+    /// user function, e.g. `F(X,Y)`
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// rebuild the expression from the RPN tokens of a symbolic
+    pub fn from_tokens(tokens: &[Obj], extable: &Extable) -> Option<Self> {
+        let mut stack: Vec<Expr> = Vec::new();
+        let pop_n = |stack: &mut Vec<Expr>, n: usize| -> Option<Vec<Expr>> {
+            (stack.len() >= n).then(|| stack.split_off(stack.len() - n))
+        };
+        for token in tokens {
+            let expr = match token {
+                Obj::Real(_) | Obj::Int(_) | Obj::Complex(_) | Obj::LongReal(_) | Obj::ExtReal(_) => {
+                    Expr::Number(token.decompile(extable))
+                }
+                Obj::GlobalName(n) | Obj::LocalName(n) => Expr::Name(n.clone()),
+                Obj::Unit(tokens) => {
+                    let unit = Unit::from_tokens(tokens, extable)?;
+                    Expr::Unit(unit.value.decompile(extable), unit.unit)
+                }
+                Obj::Ext(addr) => {
+                    let name = extable.addr_to_name.get(addr)?;
+                    if name == "xFCNAPPLY" {
+                        let name = match stack.pop()? {
+                            Expr::Name(n) => n,
+                            _ => return None,
+                        };
+                        let count = match stack.pop()? {
+                            Expr::Number(n) => n.trim_end_matches('.').parse().ok()?,
+                            _ => return None,
+                        };
+                        Expr::Call(name, pop_n(&mut stack, count)?)
+                    } else {
+                        let op = Operator::from_name(name)?;
+                        Expr::Apply(op, pop_n(&mut stack, op.arity)?)
+                    }
+                }
+                // the argument count of user functions
+                Obj::Bint(n) => Expr::Number(n.to_string()),
+                Obj::Symb(_) | Obj::CStr(_) | Obj::Tagged(..) => Expr::Atom(token.decompile(extable)),
+                _ => return None,
+            };
+            stack.push(expr);
+        }
+        match (stack.pop(), stack.is_empty()) {
+            (Some(expr), true) => Some(expr),
+            _ => None,
+        }
+    }
+
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Expr::Apply(op, _) => op.precedence,
+            // negative numbers bind like a negation
+            Expr::Number(n) if n.starts_with('-') => NEGATION,
+            _ => ATOM,
+        }
+    }
+}
+
+fn write_args(f: &mut fmt::Formatter, args: &[Expr]) -> fmt::Result {
+    for (i, a) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", a)?;
+    }
+    Ok(())
+}
+
+// operand, in parentheses when it binds looser than its operator allows
+fn write_operand(f: &mut fmt::Formatter, e: &Expr, min_precedence: u8) -> fmt::Result {
+    if e.precedence() < min_precedence {
+        write!(f, "({})", e)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(s) | Expr::Name(s) | Expr::Atom(s) => write!(f, "{}", s),
+            Expr::Unit(value, unit) => write!(f, "{}_{}", value, unit),
+            Expr::Call(name, args) => {
+                write!(f, "{}(", name)?;
+                write_args(f, args)?;
+                write!(f, ")")
+            }
+            Expr::Apply(op, args) => {
+                let p = op.precedence;
+                match (op.fixity, op.name) {
+                    (Fixity::Infix, _) => {
+                        write_operand(f, &args[0], p)?;
+                        write!(f, "{}", op.symbol)?;
+                        write_operand(f, &args[1], p + 1)
+                    }
+                    (Fixity::InfixRight, _) => {
+                        write_operand(f, &args[0], p + 1)?;
+                        write!(f, "{}", op.symbol)?;
+                        write_operand(f, &args[1], p)
+                    }
+                    (Fixity::Prefix, _) => {
+                        write!(f, "{}", op.symbol)?;
+                        // √ takes a single factor, -A^2 is -(A^2)
+                        write_operand(f, &args[0], if op.name == "xSQRT" { ATOM } else { p })
+                    }
+                    (Fixity::Postfix, _) => {
+                        write_operand(f, &args[0], ATOM)?;
+                        write!(f, "{}", op.symbol)
+                    }
+                    (Fixity::Constant, _) => write!(f, "{}", op.symbol),
+                    (Fixity::Function, "xDER") => write!(f, "∂{}({})", args[0], args[1]),
+                    (Fixity::Function, "xSUM") => {
+                        write!(f, "Σ({}={},", args[0], args[1])?;
+                        write_args(f, &args[2..])?;
+                        write!(f, ")")
+                    }
+                    (Fixity::Function, _) => {
+                        write!(f, "{}(", op.symbol)?;
+                        write_args(f, args)?;
+                        write!(f, ")")
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::Real;
+
+    pub(crate) fn extable() -> Extable {
+        let mut extable = Extable::default();
+        let names = OPERATORS.iter().map(|o| o.name).chain(["xFCNAPPLY"]);
+        for (addr, name) in (0x10000..).zip(names) {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        extable
+    }
+    pub(crate) fn x(name: &str) -> Obj {
+        Obj::Ext(extable().name_to_addr[name])
+    }
+    pub(crate) fn id(name: &str) -> Obj {
+        Obj::GlobalName(name.to_string())
+    }
+    pub(crate) fn real(v: f64) -> Obj {
+        Obj::Real(Real::from_float(v))
+    }
+    fn render(tokens: Vec<Obj>) -> String {
+        Obj::Symb(tokens).decompile(&extable())
+    }
+
+    #[test]
+    fn test_pythagoras() {
+        let int = |v: i128| Obj::Int(crate::ZInt::from(v));
+        let tokens = vec![
+            id("X"),
+            x("xSIN"),
+            int(2),
+            x("x^"),
+            id("X"),
+            x("xCOS"),
+            int(2),
+            x("x^"),
+            x("x+"),
+            int(1),
+            x("x="),
+        ];
+        assert_eq!(render(tokens), "'SIN(X)^2+COS(X)^2=1'");
+    }
+
+    #[test]
+    fn test_parentheses() {
+        // (A+B)*C, A-(B-C), A-B-C, A^B^C, (A^B)^C
+        assert_eq!(render(vec![id("A"), id("B"), x("x+"), id("C"), x("x*")]), "'(A+B)*C'");
+        assert_eq!(render(vec![id("A"), id("B"), id("C"), x("x-"), x("x-")]), "'A-(B-C)'");
+        assert_eq!(render(vec![id("A"), id("B"), x("x-"), id("C"), x("x-")]), "'A-B-C'");
+        assert_eq!(render(vec![id("A"), id("B"), id("C"), x("x^"), x("x^")]), "'A^B^C'");
+        assert_eq!(render(vec![id("A"), id("B"), x("x^"), id("C"), x("x^")]), "'(A^B)^C'");
+        // -A^2, (-A)^2, √(A+1), N!
+        assert_eq!(render(vec![id("A"), real(2.0), x("x^"), x("xNEG")]), "'-A^2.'");
+        assert_eq!(render(vec![id("A"), x("xNEG"), real(2.0), x("x^")]), "'(-A)^2.'");
+        assert_eq!(render(vec![id("A"), real(1.0), x("x+"), x("xSQRT")]), "'√(A+1.)'");
+        assert_eq!(render(vec![id("N"), x("xFACT")]), "'N!'");
+    }
+
+    #[test]
+    fn test_logic() {
+        // relations bind tighter than AND, which binds tighter than OR
+        let tokens = vec![id("A"), real(1.0), x("x<"), id("B"), real(2.0), x("x>"), x("xAND")];
+        assert_eq!(render(tokens), "'A<1. AND B>2.'");
+        let tokens = vec![id("A"), id("B"), x("xOR"), id("C"), x("xAND")];
+        assert_eq!(render(tokens), "'(A OR B) AND C'");
+        let tokens = vec![id("A"), id("B"), x("xAND"), id("C"), x("x==")];
+        assert_eq!(render(tokens), "'(A AND B)==C'");
+    }
+
+    #[test]
+    fn test_functions() {
+        // F(X,2)+π with a local X
+        let tokens = vec![
+            Obj::LocalName("x".to_string()),
+            real(2.0),
+            Obj::Bint(2),
+            id("F"),
+            x("xFCNAPPLY"),
+            x("xPI"),
+            x("x+"),
+        ];
+        assert_eq!(render(tokens), "'F(x,2.)+π'");
+        let tokens = vec![id("X"), id("X"), x("xSIN"), x("xDER")];
+        assert_eq!(render(tokens), "'∂X(SIN(X))'");
+        let tokens = vec![id("I"), real(1.0), real(10.0), id("I"), real(2.0), x("x^"), x("xSUM")];
+        assert_eq!(render(tokens), "'Σ(I=1.,10.,I^2.)'");
+        let tokens = vec![real(0.0), real(1.0), id("X"), id("X"), x("xINTEGRAL")];
+        assert_eq!(render(tokens), "'∫(0.,1.,X,X)'");
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(Expr::from_tokens(&[id("A"), x("x+")], &extable()).is_none());
+        assert!(Expr::from_tokens(&[id("A"), id("B")], &extable()).is_none());
+        assert!(Expr::from_tokens(&[id("A"), x("xSIN")], &Extable::default()).is_none());
+    }
+}
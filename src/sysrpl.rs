@@ -0,0 +1,129 @@
+//! SysRPL decompiler
+//!
+//! Composite objects are written one token per line, indented by their nesting:
+//!
+//! ```text
+//! ::
+//!   ID X
+//!   %1
+//!   x+
+//! ;
+//! ```
+//!
+//! Entries that are not in the extable are written as `PTR addr`.
+use crate::commands::quote_string;
+use crate::decompile::{Decompiled, Dialect};
+use crate::disasm::disassemble;
+use crate::{Extable, Obj};
+
+const INDENT: &str = "  ";
+
+/// SysRPL source of an object, without the MASD header
+pub(crate) fn sysrpl(obj: &Obj, extable: &Extable, dialect: Dialect) -> String {
+    let mut lines = Vec::new();
+    write_obj(obj, extable, dialect, 0, &mut lines);
+    lines.join("\n")
+}
+
+fn push(lines: &mut Vec<String>, depth: usize, line: String) {
+    lines.push(format!("{}{}", INDENT.repeat(depth), line));
+}
+
+fn write_composite(open: &str, tokens: &[Obj], close: &str, extable: &Extable, dialect: Dialect, depth: usize, lines: &mut Vec<String>) {
+    push(lines, depth, open.to_string());
+    for t in tokens {
+        write_obj(t, extable, dialect, depth + 1, lines);
+    }
+    push(lines, depth, close.to_string());
+}
+
+fn write_obj(obj: &Obj, extable: &Extable, dialect: Dialect, depth: usize, lines: &mut Vec<String>) {
+    match obj {
+        Obj::Prg(v) => write_composite("::", v, ";", extable, dialect, depth, lines),
+        Obj::List(v) => write_composite("{", v, "}", extable, dialect, depth, lines),
+        Obj::Symb(v) => write_composite("SYMBOL", v, ";", extable, dialect, depth, lines),
+        Obj::Unit(v) => write_composite("UNIT", v, ";", extable, dialect, depth, lines),
+        Obj::Matrix(v) => write_composite("MATRIX", v, ";", extable, dialect, depth, lines),
+        Obj::Tagged(tag, obj) => {
+            push(lines, depth, format!("TAG {}", tag));
+            write_obj(obj, extable, dialect, depth + 1, lines);
+        }
+        Obj::Code(blob) => {
+            push(lines, depth, "CODE".to_string());
+            // labels stay in the first column
+            lines.extend(disassemble(&blob.0, extable, dialect).lines().map(str::to_string));
+            push(lines, depth, "ENDCODE".to_string());
+        }
+        // strings keep their line breaks as they are
+        _ => push(lines, depth, atom(obj, extable, dialect)),
+    }
+}
+
+fn atom(obj: &Obj, extable: &Extable, dialect: Dialect) -> String {
+    match obj {
+        Obj::GlobalName(n) => format!("ID {}", n),
+        Obj::LocalName(n) => format!("LAM {}", n),
+        Obj::CStr(s) => format!("$ {}", quote_string(&s.0)),
+        Obj::Real(r) => format!("% {}", r.decompile(extable)),
+        Obj::Complex(c) => format!("C% {} {}", c.real.decompile(extable), c.imag.decompile(extable)),
+        Obj::Int(i) => format!("ZINT {}", i.decompile(extable)),
+        Obj::Bint(b) => format!("# {:X}", b),
+        Obj::HexString(h) => h.to_hxs(),
+        Obj::Ext(addr) => match extable.addr_to_name.get(addr) {
+            Some(name) => name.clone(),
+            None => format!("PTR {:05X}", addr),
+        },
+        Obj::RomPtr(lib, cmd) => match dialect {
+            Dialect::Jazz => format!("ROMPTR {:03X} {:03X}", lib, cmd),
+            Dialect::Masd => format!("ROMPTR {:X} {:X}", lib, cmd),
+        },
+        Obj::FlashPtr(bank, cmd) => match dialect {
+            Dialect::Jazz => format!("FPTR {:X} {:X}", bank, cmd),
+            Dialect::Masd => format!("FLASHPTR {:X} {:X}", bank, cmd),
+        },
+        _ => obj.decompile(extable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Real, StringBlob, ZInt};
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        extable.name_to_addr.insert("x+".to_string(), 0x3d0f7);
+        extable.addr_to_name.insert(0x3d0f7, "x+".to_string());
+        extable
+    }
+
+    #[test]
+    fn test_sysrpl_program() {
+        let prg = Obj::Prg(vec![
+            Obj::GlobalName("X".to_string()),
+            Obj::Real(Real::from_float(1.5)),
+            Obj::Ext(0x3d0f7),
+            Obj::Ext(0x4e2cf),
+            Obj::List(vec![Obj::LocalName("n".to_string()), Obj::Int(ZInt::from(-12))]),
+            Obj::Tagged("T".to_string(), Box::new(Obj::CStr(StringBlob("a\nb".to_string())))),
+            Obj::Bint(0x1f),
+        ]);
+        let expected = "::\n  ID X\n  % 1.5\n  x+\n  PTR 4E2CF\n  {\n    LAM n\n    ZINT -12\n  }\n  TAG T\n    $ \"a\nb\"\n  # 1F\n;";
+        assert_eq!(sysrpl(&prg, &extable(), Dialect::Jazz), expected);
+        // programs without x<< are SysRPL
+        assert_eq!(prg.decompile(&extable()), expected);
+    }
+
+    #[test]
+    fn test_dialects() {
+        let prg = Obj::Prg(vec![Obj::RomPtr(0x2f0, 0x1a), Obj::FlashPtr(2, 0x6e), Obj::Code(crate::Blob(vec![1, 0xa]))]);
+        assert_eq!(
+            sysrpl(&prg, &extable(), Dialect::Jazz),
+            "::\n  ROMPTR 2F0 01A\n  FPTR 2 6E\n  CODE\n  NIBHEX 1A\n  ENDCODE\n;"
+        );
+        assert_eq!(
+            prg.decompile_sysrpl(&extable(), Dialect::Masd),
+            "!NO CODE\n!RPL\n::\n  ROMPTR 2F0 1A\n  FLASHPTR 2 6E\n  CODE\n  $1A\n  ENDCODE\n;\n@"
+        );
+    }
+}
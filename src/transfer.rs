@@ -0,0 +1,974 @@
+//! Text transfer files
+//!
+//! Objects sent in text mode start with a header giving the translation mode,
+//! the angle mode and the fraction mark, followed by the object as typed on the
+//! calculator:
+//!
+//! ```text
+//! %%HP: T(3)A(R)F(.);
+//! « → a b 'a^2+b^2' »
+//! ```
+//!
+//! The text is compiled to the objects the calculator would build: commands are
+//! named through the extable, names bound by `→` or `FOR` are locals, and
+//! algebraics are stored in RPN.
+use std::fmt;
+use std::path::Path;
+
+use crate::commands::{extable_name, unquote_string};
+use crate::consts::{DOCMP, DOREAL};
+use crate::symbolic::{Fixity, Operator, ATOM, OPERATORS};
+use crate::trigraph::untranslate;
+use crate::{charset, Array, Complex, Decimal, Error, Extable, HexString, Obj, Real, StringBlob, ZInt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AngleMode {
+    Degrees,
+    #[default]
+    Radians,
+    Grads,
+}
+
+/// Fields of the `%%HP:` header line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferHeader {
+    /// T(0) to T(3): which characters are written as escapes
+    pub translation: u8,
+    pub angle: AngleMode,
+    /// `.` or `,`, with `,` the separator of complex numbers and arguments is `;`
+    pub fraction_mark: char,
+}
+
+impl Default for TransferHeader {
+    fn default() -> Self {
+        TransferHeader { translation: 3, angle: AngleMode::Radians, fraction_mark: '.' }
+    }
+}
+
+impl TransferHeader {
+    /// Parse `%%HP: T(3)A(R)F(.);`, fields that are missing keep their default
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields = line.trim().strip_prefix("%%HP:")?;
+        let fields = fields.split(';').next()?;
+        let mut header = TransferHeader::default();
+        let mut rest = fields.trim();
+        while !rest.is_empty() {
+            let (field, tail) = rest.split_once(')')?;
+            let (name, value) = field.trim().split_once('(')?;
+            match (name, value) {
+                ("T", t) => header.translation = t.parse().ok().filter(|t| *t <= 3)?,
+                ("A", "D") => header.angle = AngleMode::Degrees,
+                ("A", "R") => header.angle = AngleMode::Radians,
+                ("A", "G") => header.angle = AngleMode::Grads,
+                ("F", ".") => header.fraction_mark = '.',
+                ("F", ",") => header.fraction_mark = ',',
+                _ => return None,
+            }
+            rest = tail.trim();
+        }
+        Some(header)
+    }
+
+    fn separator(&self) -> char {
+        if self.fraction_mark == ',' {
+            ';'
+        } else {
+            ','
+        }
+    }
+}
+
+impl fmt::Display for TransferHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let angle = match self.angle {
+            AngleMode::Degrees => 'D',
+            AngleMode::Radians => 'R',
+            AngleMode::Grads => 'G',
+        };
+        write!(f, "%%HP: T({})A({})F({});", self.translation, angle, self.fraction_mark)
+    }
+}
+
+// structures whose closing words depend on the opening one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Structure {
+    If,
+    IfErr,
+    Case,
+    /// a CASE clause, after its THEN
+    Clause,
+    Do,
+    While,
+    /// START or FOR
+    Loop,
+    /// FOR, whose variable is a local
+    For,
+}
+
+const DELIMITERS: &str = "«»{}[]\"'";
+// characters that end a name in algebraics
+const ALGEBRAIC_DELIMITERS: &str = "+-*/^=<>≤≥≠|(),;!'√_∂";
+
+struct TextParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    extable: &'a Extable,
+    header: TransferHeader,
+    /// names of the enclosing locals
+    locals: Vec<Vec<String>>,
+}
+
+impl TextParser<'_> {
+    fn error(&self, message: String) -> Error {
+        let line = self.chars[..self.pos.min(self.chars.len())].iter().filter(|c| **c == '\n').count() + 1;
+        Error::CompileError(format!("line {}: {}", line, message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        self.skip_space();
+        if self.peek() != Some(c) {
+            return Err(self.error(format!("expected {}", c)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // whitespace and @ comments, which end at the next @ or at the end of the line
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '@' {
+                self.pos += 1;
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '@' || c == '\n' {
+                        break;
+                    }
+                }
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && !DELIMITERS.contains(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn ext(&self, name: &str) -> Result<Obj, Error> {
+        match self.extable.name_to_addr.get(name) {
+            Some(addr) => Ok(Obj::Ext(*addr)),
+            None => Err(self.error(format!("{} is missing from the extable", name))),
+        }
+    }
+
+    // the first of these entries that the extable has
+    fn first_ext(&self, names: &[&str]) -> Result<Obj, Error> {
+        names.iter().find_map(|n| self.ext(n).ok()).ok_or_else(|| self.error(format!("{} is missing from the extable", names[0])))
+    }
+
+    fn name(&self, name: &str) -> Obj {
+        if self.locals.iter().any(|scope| scope.iter().any(|n| n == name)) {
+            Obj::LocalName(name.to_string())
+        } else {
+            Obj::GlobalName(name.to_string())
+        }
+    }
+
+    fn decimal(&self, text: &str) -> Option<Decimal> {
+        let text = if self.header.fraction_mark == ',' { text.replace(',', ".") } else { text.to_string() };
+        if text.contains(',') {
+            return None;
+        }
+        text.parse().ok()
+    }
+
+    // integers without fraction mark are exact, other numbers are reals
+    fn number(&self, text: &str) -> Option<Obj> {
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            return text.parse::<ZInt>().ok().map(Obj::Int);
+        }
+        self.decimal(text).map(|d| Obj::Real(Real::from_decimal(d)))
+    }
+
+    fn word_object(&self, word: &str) -> Result<Obj, Error> {
+        if let Some((value, unit)) = word.split_once('_') {
+            if let Some(value) = self.number(value) {
+                let mut tokens = vec![value];
+                self.unit(unit, &mut tokens)?;
+                tokens.push(self.ext("umEND")?);
+                return Ok(Obj::Unit(tokens));
+            }
+        }
+        if let Some(number) = self.number(word) {
+            return Ok(number);
+        }
+        if self.locals.iter().any(|scope| scope.iter().any(|n| n == word)) {
+            return Ok(Obj::LocalName(word.to_string()));
+        }
+        if let Some(name) = extable_name(self.extable, word) {
+            return self.ext(name);
+        }
+        Ok(Obj::GlobalName(word.to_string()))
+    }
+
+    // unit expression in RPN: names, then um* and um/, and um^ after an exponent
+    fn unit(&self, unit: &str, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        let chars: Vec<char> = unit.chars().collect();
+        let mut pos = 0;
+        self.unit_product(&chars, &mut pos, tokens)?;
+        if pos < chars.len() {
+            return Err(self.error(format!("bad unit {}", unit)));
+        }
+        Ok(())
+    }
+
+    fn unit_product(&self, chars: &[char], pos: &mut usize, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        self.unit_power(chars, pos, tokens)?;
+        while let Some(op @ ('*' | '/')) = chars.get(*pos) {
+            *pos += 1;
+            self.unit_power(chars, pos, tokens)?;
+            tokens.push(self.ext(if *op == '*' { "um*" } else { "um/" })?);
+        }
+        Ok(())
+    }
+
+    fn unit_power(&self, chars: &[char], pos: &mut usize, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        if chars.get(*pos) == Some(&'(') {
+            *pos += 1;
+            self.unit_product(chars, pos, tokens)?;
+            if chars.get(*pos) != Some(&')') {
+                return Err(self.error("unbalanced unit".to_string()));
+            }
+            *pos += 1;
+        } else {
+            let start = *pos;
+            while chars.get(*pos).is_some_and(|c| !"*/^()".contains(*c)) {
+                *pos += 1;
+            }
+            if start == *pos {
+                return Err(self.error("missing unit".to_string()));
+            }
+            tokens.push(Obj::CStr(StringBlob(chars[start..*pos].iter().collect())));
+        }
+        if chars.get(*pos) == Some(&'^') {
+            *pos += 1;
+            let start = *pos;
+            while chars.get(*pos).is_some_and(|c| c.is_ascii_digit() || *c == '-' || *c == self.header.fraction_mark) {
+                *pos += 1;
+            }
+            let exponent: String = chars[start..*pos].iter().collect();
+            let d = self.decimal(&exponent).ok_or_else(|| self.error(format!("bad exponent {}", exponent)))?;
+            tokens.push(Obj::Real(Real::from_decimal(d)));
+            tokens.push(self.ext("um^")?);
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<Obj, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    let literal: String = self.chars[start..self.pos].iter().collect();
+                    let s = unquote_string(&literal).ok_or_else(|| self.error("bad string".to_string()))?;
+                    return Ok(Obj::CStr(StringBlob(s)));
+                }
+                _ => {}
+            }
+        }
+        self.pos = start;
+        Err(self.error("unterminated string".to_string()))
+    }
+
+    // (re,im), or (r,∡θ) in polar form, in the angle mode of the header
+    fn complex(&mut self) -> Result<Obj, Error> {
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        self.expect(')')?;
+        let bad = || self.error(format!("bad complex number ({})", text));
+        let (re, im) = text.split_once(self.header.separator()).ok_or_else(bad)?;
+        let re = self.decimal(re.trim()).ok_or_else(bad)?;
+        let im = im.trim();
+        let (re, im) = match im.strip_prefix(['∡', '∠']) {
+            Some(angle) => {
+                let angle = self.decimal(angle.trim()).ok_or_else(bad)?.as_float();
+                let quarter = match self.header.angle {
+                    AngleMode::Degrees => 90.0,
+                    AngleMode::Radians => std::f64::consts::FRAC_PI_2,
+                    AngleMode::Grads => 100.0,
+                };
+                let turns = angle / quarter;
+                // quarter turns are exact, π to 12 digits included
+                if (turns - turns.round()).abs() < 1e-11 {
+                    let zero = Decimal::default();
+                    let minus = Decimal::new(!re.negative, re.significand, re.exponent);
+                    let (x, y) = match (turns.round() as i64).rem_euclid(4) {
+                        0 => (re, zero),
+                        1 => (zero, re),
+                        2 => (minus, zero),
+                        _ => (zero, minus),
+                    };
+                    (Real::from_decimal(x), Real::from_decimal(y))
+                } else {
+                    // rounding to 12 digits drops the float noise
+                    let radians = turns * std::f64::consts::FRAC_PI_2;
+                    let r = re.as_float();
+                    (Real::from_float(r * radians.cos()), Real::from_float(r * radians.sin()))
+                }
+            }
+            None => (Real::from_decimal(re), Real::from_decimal(self.decimal(im).ok_or_else(bad)?)),
+        };
+        Ok(Obj::Complex(Complex { real: re, imag: im }))
+    }
+
+    // # 1Fh, in the base of the suffix, decimal by default. The suffix is lowercase and
+    // the digits uppercase, so # 1Bh and # 1b differ. User binary integers are always
+    // stored in 64 bits, the word size only changes how they are shown
+    fn binary(&mut self) -> Result<Obj, Error> {
+        self.pos += 1;
+        self.skip_space();
+        let word = self.word();
+        let (digits, radix) = match word.chars().last() {
+            Some('h') => (&word[..word.len() - 1], 16),
+            Some('d') => (&word[..word.len() - 1], 10),
+            Some('o') => (&word[..word.len() - 1], 8),
+            Some('b') => (&word[..word.len() - 1], 2),
+            _ => (word.as_str(), 10),
+        };
+        let bad = || self.error(format!("bad binary integer # {}", word));
+        if digits.chars().any(|c| c.is_ascii_lowercase()) {
+            return Err(bad());
+        }
+        let value = u64::from_str_radix(digits, radix).map_err(|_| bad())?;
+        Ok(Obj::HexString(HexString((0..16).map(|i| ((value >> (4 * i)) & 0xf) as u8).collect())))
+    }
+
+    // [ 1. 2. ] or [[ 1. 2. ][ 3. 4. ]]: arrays of reals or complex numbers,
+    // symbolic matrices otherwise
+    fn array(&mut self) -> Result<Obj, Error> {
+        self.pos += 1;
+        self.skip_space();
+        let (elements, dims) = if self.peek() == Some('[') {
+            let mut rows: Vec<Vec<Obj>> = Vec::new();
+            loop {
+                self.skip_space();
+                match self.peek() {
+                    Some('[') => {
+                        self.pos += 1;
+                        rows.push(self.sequence(']', |p| p.object())?);
+                    }
+                    Some(']') => {
+                        self.pos += 1;
+                        break;
+                    }
+                    _ => return Err(self.error("[ without ]".to_string())),
+                }
+            }
+            let cols = rows.first().map_or(0, |r| r.len());
+            if rows.iter().any(|r| r.len() != cols) {
+                return Err(self.error("rows of different lengths".to_string()));
+            }
+            let dims = vec![rows.len(), cols];
+            (rows.into_iter().flatten().collect::<Vec<_>>(), dims)
+        } else {
+            let elements = self.sequence(']', |p| p.object())?;
+            let dims = vec![elements.len()];
+            (elements, dims)
+        };
+        let obj_type = if elements.iter().all(|e| matches!(e, Obj::Real(_))) {
+            Some(DOREAL)
+        } else if elements.iter().all(|e| matches!(e, Obj::Complex(_))) {
+            Some(DOCMP)
+        } else {
+            None
+        };
+        match obj_type {
+            Some(obj_type) if !elements.is_empty() => {
+                Ok(Obj::Array(Array { obj_type, num_dims: dims.len(), dims, objects: elements, links: None }))
+            }
+            _ if dims.len() == 2 => {
+                let mut elements = elements.into_iter();
+                let rows = (0..dims[0]).map(|_| Obj::Matrix(elements.by_ref().take(dims[1]).collect())).collect();
+                Ok(Obj::Matrix(rows))
+            }
+            _ => Ok(Obj::Matrix(elements)),
+        }
+    }
+
+    // objects up to the closing character
+    fn sequence(&mut self, close: char, mut item: impl FnMut(&mut Self) -> Result<Obj, Error>) -> Result<Vec<Obj>, Error> {
+        let mut objs = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(objs);
+                }
+                Some(_) => objs.push(item(self)?),
+                None => return Err(self.error(format!("missing {}", close))),
+            }
+        }
+    }
+
+    /// An object outside of programs
+    fn object(&mut self) -> Result<Obj, Error> {
+        self.skip_space();
+        match self.peek() {
+            None => Err(self.error("missing object".to_string())),
+            Some('«') => {
+                self.pos += 1;
+                self.program("x>>")
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.sequence('}', |p| p.object()).map(Obj::List)
+            }
+            Some('[') => self.array(),
+            Some('"') => self.string(),
+            Some('\'') => self.quoted(),
+            Some('(') => self.complex(),
+            Some('#') => self.binary(),
+            Some(':') => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != ':') {
+                    self.pos += 1;
+                }
+                let tag: String = self.chars[start..self.pos].iter().collect();
+                self.expect(':')?;
+                Ok(Obj::Tagged(tag, Box::new(self.object()?)))
+            }
+            Some(c) if DELIMITERS.contains(c) => Err(self.error(format!("unexpected {}", c))),
+            Some(_) => {
+                let word = self.word();
+                self.word_object(&word)
+            }
+        }
+    }
+
+    // the keywords of structures compile to different commands depending on the structure
+    fn keyword(&mut self, word: &str, structures: &mut Vec<Structure>, tokens: &mut Vec<Obj>) -> Result<bool, Error> {
+        let top = structures.last().copied();
+        let name = match (word, top) {
+            ("IF", _) => {
+                structures.push(Structure::If);
+                "xIF"
+            }
+            ("IFERR", _) => {
+                structures.push(Structure::IfErr);
+                "xIFERR"
+            }
+            ("CASE", _) => {
+                structures.push(Structure::Case);
+                "xCASE"
+            }
+            ("DO", _) => {
+                structures.push(Structure::Do);
+                "xDO"
+            }
+            ("WHILE", _) => {
+                structures.push(Structure::While);
+                "xWHILE"
+            }
+            ("START", _) => {
+                structures.push(Structure::Loop);
+                "xSTART"
+            }
+            ("FOR", _) => {
+                tokens.push(self.first_ext(&["xSTARTVAR", "xFOR"])?);
+                self.skip_space();
+                let variable = self.word();
+                if variable.is_empty() {
+                    return Err(self.error("FOR without its variable".to_string()));
+                }
+                tokens.push(Obj::LocalName(variable.clone()));
+                self.locals.push(vec![variable]);
+                structures.push(Structure::For);
+                return Ok(true);
+            }
+            ("THEN", Some(Structure::If)) => "xTHEN",
+            ("THEN", Some(Structure::IfErr)) => "xERRTHEN",
+            ("THEN", Some(Structure::Case)) => {
+                structures.push(Structure::Clause);
+                "xTHENCASE"
+            }
+            ("ELSE", Some(Structure::If | Structure::IfErr)) => "xELSE",
+            ("UNTIL", Some(Structure::Do)) => "xUNTIL",
+            ("REPEAT", Some(Structure::While)) => "xREPEAT",
+            ("END", Some(Structure::If | Structure::IfErr | Structure::Case | Structure::Clause)) => {
+                structures.pop();
+                "xIFEND"
+            }
+            ("END", Some(Structure::Do)) => {
+                structures.pop();
+                "xENDDO"
+            }
+            ("END", Some(Structure::While)) => {
+                structures.pop();
+                "xWHILEEND"
+            }
+            ("NEXT" | "STEP", Some(Structure::Loop | Structure::For)) => {
+                if structures.pop() == Some(Structure::For) {
+                    self.locals.pop();
+                }
+                if word == "NEXT" {
+                    "xNEXT"
+                } else {
+                    "xSTEP"
+                }
+            }
+            ("THEN" | "ELSE" | "UNTIL" | "REPEAT" | "END" | "NEXT" | "STEP", _) => {
+                return Err(self.error(format!("unexpected {}", word)));
+            }
+            _ => return Ok(false),
+        };
+        tokens.push(self.ext(name)?);
+        Ok(true)
+    }
+
+    // after the «, up to the », which compiles to `end`
+    fn program(&mut self, end: &str) -> Result<Obj, Error> {
+        let mut tokens = vec![self.ext("x<<")?];
+        let mut structures = Vec::new();
+        let locals = self.locals.len();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None => return Err(self.error("« without »".to_string())),
+                Some('»') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('\'') => {
+                    let quoted = self.quoted()?;
+                    // quoted names are preceded by a quote command, algebraics quote themselves
+                    if matches!(quoted, Obj::GlobalName(_) | Obj::LocalName(_)) {
+                        tokens.push(self.first_ext(&["xSILENT'", "x'", "'"])?);
+                    }
+                    tokens.push(quoted);
+                }
+                Some(c) if DELIMITERS.contains(c) => tokens.push(self.object()?),
+                Some(_) => {
+                    let start = self.pos;
+                    let word = self.word();
+                    if word == "→" {
+                        self.arrow(&mut tokens)?;
+                    } else if !self.keyword(&word, &mut structures, &mut tokens)? {
+                        self.pos = start;
+                        tokens.push(self.object()?);
+                    }
+                }
+            }
+        }
+        if !structures.is_empty() {
+            return Err(self.error("unterminated structure".to_string()));
+        }
+        self.locals.truncate(locals);
+        tokens.push(self.ext(end)?);
+        Ok(Obj::Prg(tokens))
+    }
+
+    // → a b « … » or → a b 'algebraic'
+    fn arrow(&mut self, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        tokens.push(self.first_ext(&["xRPN->", "x->"])?);
+        let mut names = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some('«' | '\'') => break,
+                Some(c) if !DELIMITERS.contains(c) => {
+                    let name = self.word();
+                    tokens.push(Obj::LocalName(name.clone()));
+                    names.push(name);
+                }
+                _ => return Err(self.error("→ without a program or an algebraic".to_string())),
+            }
+        }
+        self.locals.push(names);
+        let body = if self.peek() == Some('«') {
+            self.pos += 1;
+            self.program("x>>ABND")
+        } else {
+            self.quoted()
+        };
+        self.locals.pop();
+        tokens.push(body?);
+        Ok(())
+    }
+
+    // 'X' is a name, anything else an algebraic
+    fn quoted(&mut self) -> Result<Obj, Error> {
+        self.pos += 1;
+        let mut tokens = Vec::new();
+        self.algebraic(0, &mut tokens)?;
+        self.skip_space();
+        if self.peek() != Some('\'') {
+            return Err(self.error("unterminated algebraic".to_string()));
+        }
+        self.pos += 1;
+        match tokens.as_slice() {
+            [Obj::GlobalName(_) | Obj::LocalName(_)] => Ok(tokens.pop().unwrap()),
+            _ => Ok(Obj::Symb(tokens)),
+        }
+    }
+
+    fn operator(&self, symbol: &str, fixity: &[Fixity]) -> Option<&'static Operator> {
+        OPERATORS.iter().find(|o| o.symbol.trim() == symbol && fixity.contains(&o.fixity))
+    }
+
+    // infix operator at the current position, and its length
+    fn infix(&self) -> Option<(&'static Operator, usize)> {
+        let rest: String = self.chars[self.pos..].iter().take(4).collect();
+        for word in ["AND", "OR", "XOR"] {
+            if rest.starts_with(word) && !rest[word.len()..].starts_with(|c: char| c.is_alphanumeric()) {
+                return Some((self.operator(word, &[Fixity::Infix])?, word.len()));
+            }
+        }
+        let symbol = if rest.starts_with("==") { "==" } else { rest.get(..rest.chars().next()?.len_utf8())? };
+        let op = self.operator(symbol, &[Fixity::Infix, Fixity::InfixRight])?;
+        Some((op, symbol.chars().count()))
+    }
+
+    fn algebraic_name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && !ALGEBRAIC_DELIMITERS.contains(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // arguments of a function call, after the opening parenthesis
+    fn arguments(&mut self, tokens: &mut Vec<Obj>) -> Result<usize, Error> {
+        let mut count = 0;
+        loop {
+            self.algebraic(0, tokens)?;
+            count += 1;
+            self.skip_space();
+            match self.peek() {
+                Some(')') => {
+                    self.pos += 1;
+                    return Ok(count);
+                }
+                Some(c) if c == self.header.separator() => self.pos += 1,
+                _ => return Err(self.error("unterminated arguments".to_string())),
+            }
+        }
+    }
+
+    // precedence climbing, the operands are written before their operator
+    fn algebraic(&mut self, min_precedence: u8, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        self.operand(tokens)?;
+        loop {
+            self.skip_space();
+            if self.peek() == Some('!') {
+                self.pos += 1;
+                tokens.push(self.ext("xFACT")?);
+                continue;
+            }
+            let Some((op, len)) = self.infix() else {
+                return Ok(());
+            };
+            if op.precedence < min_precedence {
+                return Ok(());
+            }
+            self.pos += len;
+            let next = if op.fixity == Fixity::InfixRight { op.precedence } else { op.precedence + 1 };
+            self.algebraic(next, tokens)?;
+            tokens.push(self.ext(op.name)?);
+        }
+    }
+
+    fn operand(&mut self, tokens: &mut Vec<Obj>) -> Result<(), Error> {
+        self.skip_space();
+        let Some(c) = self.peek() else {
+            return Err(self.error("missing operand".to_string()));
+        };
+        match c {
+            '(' => {
+                self.pos += 1;
+                self.algebraic(0, tokens)?;
+                self.expect(')')?;
+            }
+            '-' | '√' => {
+                self.pos += 1;
+                let op = self.operator(&c.to_string(), &[Fixity::Prefix]).ok_or_else(|| self.error(format!("unexpected {}", c)))?;
+                // √ applies to the next atom
+                let precedence = if c == '√' { ATOM } else { op.precedence };
+                self.algebraic(precedence, tokens)?;
+                tokens.push(self.ext(op.name)?);
+            }
+            '∂' => {
+                // ∂X(F) is stored as X F xDER
+                self.pos += 1;
+                let variable = self.algebraic_name();
+                tokens.push(self.name(&variable));
+                self.expect('(')?;
+                self.algebraic(0, tokens)?;
+                self.expect(')')?;
+                tokens.push(self.ext("xDER")?);
+            }
+            'Σ' if self.peek_at(1) == Some('(') => {
+                // Σ(I=A,B,F) is stored as I A B F xSUM
+                self.pos += 2;
+                self.skip_space();
+                let index = self.algebraic_name();
+                tokens.push(self.name(&index));
+                self.expect('=')?;
+                if self.arguments(tokens)? != 3 {
+                    return Err(self.error("Σ needs 3 arguments".to_string()));
+                }
+                tokens.push(self.ext("xSUM")?);
+            }
+            c if c.is_ascii_digit() || (c == self.header.fraction_mark && self.peek_at(1).is_some_and(|d| d.is_ascii_digit())) => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == self.header.fraction_mark) {
+                    self.pos += 1;
+                }
+                if self.peek() == Some('E') && self.peek_at(1).is_some_and(|c| c.is_ascii_digit() || c == '-' || c == '+') {
+                    self.pos += 2;
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.pos += 1;
+                    }
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                let number = self.number(&text).ok_or_else(|| self.error(format!("bad number {}", text)))?;
+                if self.peek() == Some('_') {
+                    // a unit, its name and an exponent
+                    self.pos += 1;
+                    let start = self.pos;
+                    let _ = self.algebraic_name();
+                    if self.peek() == Some('^') {
+                        self.pos += 1;
+                        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                            self.pos += 1;
+                        }
+                    }
+                    let unit: String = self.chars[start..self.pos].iter().collect();
+                    let mut unit_tokens = vec![number];
+                    self.unit(&unit, &mut unit_tokens)?;
+                    unit_tokens.push(self.ext("umEND")?);
+                    tokens.push(Obj::Unit(unit_tokens));
+                } else {
+                    tokens.push(number);
+                }
+            }
+            _ => {
+                let name = self.algebraic_name();
+                if name.is_empty() {
+                    return Err(self.error(format!("unexpected {}", c)));
+                }
+                if name == "NOT" {
+                    let op = self.operator("NOT", &[Fixity::Prefix]).ok_or_else(|| self.error("unexpected NOT".to_string()))?;
+                    self.algebraic(op.precedence, tokens)?;
+                    tokens.push(self.ext(op.name)?);
+                    return Ok(());
+                }
+                if self.peek() == Some('(') {
+                    self.pos += 1;
+                    let count = self.arguments(tokens)?;
+                    match self.operator(&name, &[Fixity::Function]) {
+                        Some(op) if op.arity == count => tokens.push(self.ext(op.name)?),
+                        Some(op) => return Err(self.error(format!("{} needs {} arguments", name, op.arity))),
+                        None => {
+                            tokens.push(Obj::Bint(count as u32));
+                            tokens.push(Obj::GlobalName(name));
+                            tokens.push(self.ext("xFCNAPPLY")?);
+                        }
+                    }
+                } else if let Some(op) = self.operator(&name, &[Fixity::Constant]) {
+                    tokens.push(self.ext(op.name)?);
+                } else {
+                    tokens.push(self.name(&name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a text transfer, with or without its `%%HP:` header
+pub fn parse_transfer(text: &str, extable: &Extable) -> Result<Obj, Error> {
+    let text = text.trim_start_matches('\u{feff}');
+    let (header, body) = match text.trim_start().strip_prefix("%%HP:") {
+        Some(_) => {
+            let text = text.trim_start();
+            let end = text.find(';').ok_or_else(|| Error::CompileError("line 1: unterminated header".to_string()))?;
+            let header = TransferHeader::parse(&text[..=end])
+                .ok_or_else(|| Error::CompileError(format!("line 1: bad header {}", &text[..=end])))?;
+            (header, &text[end + 1..])
+        }
+        None => (TransferHeader::default(), text),
+    };
+    let body = untranslate(body, header.translation);
+    let mut parser = TextParser { chars: body.chars().collect(), pos: 0, extable, header, locals: Vec::new() };
+    let obj = parser.object()?;
+    parser.skip_space();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected text after the object".to_string()));
+    }
+    Ok(obj)
+}
+
+/// Parse a text transfer file. Files that are not UTF-8 are in the calculator charset
+pub fn parse_transfer_file(path: &Path, extable: &Extable) -> Result<Obj, Error> {
+    let bytes = std::fs::read(path)?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => charset::decode(e.as_bytes()),
+    };
+    parse_transfer(&text, extable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompile::Decompiled;
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        let names = [
+            "x<<", "x>>", "x>>ABND", "xRPN->", "xSILENT'", "x+", "x-", "x*", "x/", "x^", "x=", "xNEG", "xSIN", "xCOS",
+            "xSQRT", "xFACT", "xPI", "xDER", "xSUM", "xFCNAPPLY", "xDUP", "xSTO", "x>LIST", "xIF", "xTHEN", "xELSE",
+            "xIFEND", "xDO", "xUNTIL", "xENDDO", "xSTART", "xNEXT", "umEND", "um*", "um/", "um^",
+        ];
+        for (addr, name) in (1..).zip(names) {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        extable
+    }
+
+    fn roundtrip(text: &str) -> String {
+        parse_transfer(text, &extable()).unwrap().decompile(&extable())
+    }
+
+    #[test]
+    fn test_header() {
+        let header = TransferHeader::parse("%%HP: T(1)A(D)F(,);").unwrap();
+        assert_eq!(header, TransferHeader { translation: 1, angle: AngleMode::Degrees, fraction_mark: ',' });
+        assert_eq!(header.to_string(), "%%HP: T(1)A(D)F(,);");
+        assert_eq!(TransferHeader::parse("%%HP: T(3);"), Some(TransferHeader::default()));
+        assert_eq!(TransferHeader::parse("%%HP: T(7);"), None);
+        assert_eq!(TransferHeader::parse("HPHP49-C"), None);
+    }
+
+    #[test]
+    fn test_program() {
+        assert_eq!(
+            roundtrip("%%HP: T(3)A(R)F(.);\r\n« DUP 1. + 'X' STO \"a\\\"b\" { A →LIST } »"),
+            "« DUP 1. + 'X' STO \"a\\\"b\" { A →LIST } »"
+        );
+        assert_eq!(roundtrip("« → a b « a b + » »"), "« → a b « a b + » »");
+        let prg = parse_transfer("« → a « a » »", &extable()).unwrap();
+        match prg {
+            Obj::Prg(v) => match &v[3] {
+                Obj::Prg(body) => assert!(matches!(&body[1], Obj::LocalName(n) if n == "a")),
+                _ => panic!("{:?}", v),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_structures() {
+        assert_eq!(
+            roundtrip("« IF DUP THEN 1 ELSE 2 END DO 1 UNTIL 2 END »"),
+            "«\n  IF DUP\n  THEN 1\n  ELSE 2\n  END\n  DO 1\n  UNTIL 2\n  END\n»"
+        );
+        assert!(parse_transfer("« 1 END »", &extable()).is_err());
+        assert!(parse_transfer("« IF 1 THEN »", &extable()).is_err());
+    }
+
+    // structures compile to the entries of the real extable
+    #[test]
+    fn test_fixture_structures() {
+        let extable = crate::extable::tests::fixture();
+        let source = "« 1 3 FOR i i CASE DUP 1 == THEN 10 END 20 END NEXT »";
+        let prg = parse_transfer(source, &extable).unwrap();
+        let Obj::Prg(tokens) = &prg else { panic!("{:?}", prg) };
+        assert!(matches!(tokens[3], Obj::Ext(addr) if addr == extable.name_to_addr["xSTARTVAR"]));
+        assert!(matches!(&tokens[4], Obj::LocalName(n) if n == "i"));
+        assert_eq!(crate::locals::analyze_locals(&prg, &extable).diagnostics, vec![]);
+        assert_eq!(crate::control::check_structures(&prg, &extable), vec![]);
+        assert_eq!(
+            prg.decompile(&extable),
+            "«\n  1 3\n  FOR i\n    i\n    CASE\n      DUP 1 ==\n      THEN 10\n      END\n      20\n    END\n  NEXT\n»"
+        );
+    }
+
+    #[test]
+    fn test_algebraic() {
+        assert_eq!(roundtrip("'SIN(X)^2+COS(X)^2=1'"), "'SIN(X)^2+COS(X)^2=1'");
+        assert_eq!(roundtrip("'-(A+B)*C/√D!'"), "'-(A+B)*C/√D!'");
+        assert_eq!(roundtrip("'A-(B-C)+2^3^4'"), "'A-(B-C)+2^3^4'");
+        assert_eq!(roundtrip("'F(X,2)+π'"), "'F(X,2)+π'");
+        assert_eq!(roundtrip("'∂X(X^2)+Σ(I=1,N,I)'"), "'∂X(X^2)+Σ(I=1,N,I)'");
+        assert_eq!(roundtrip("'X'"), "'X'");
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(roundtrip("{ 1 -2. 1.5E-3 # 1Fh (1.,2.) 9.81_m/s^2 }"), "{ 1 -2. .0015 # 1Fh (1.,2.) 9.81_m/s^2 }");
+        assert_eq!(roundtrip("%%HP: T(3)A(D)F(,);\n{ 1,5 (1,5;2) }"), "{ 1.5 (1.5,2.) }");
+        assert_eq!(roundtrip("%%HP: T(3)A(D)F(.);\n(2.,∡90.)"), "(0.,2.)");
+        assert_eq!(roundtrip("%%HP: T(3)A(D)F(.);\n(2.,∡-90.)"), "(0.,-2.)");
+        assert_eq!(roundtrip("%%HP: T(3)A(G)F(.);\n(2.,∡200.)"), "(-2.,0.)");
+        assert_eq!(roundtrip("%%HP: T(3)A(R)F(.);\n(1.,∡3.14159265359)"), "(-1.,0.)");
+        assert_eq!(roundtrip("%%HP: T(3)A(D)F(.);\n(2.,∡30.)"), "(1.73205080757,1.)");
+        assert_eq!(roundtrip("[[ 1. 2. ][ 3. 4. ]]"), "[[ 1. 2. ][ 3. 4. ]]");
+        assert_eq!(roundtrip(":T:\"x\" @ comment"), ":T:\"x\"");
+        assert_eq!(roundtrip("\"\\141\""), "\"→\"");
+    }
+
+    #[test]
+    fn test_binary_integers() {
+        let value = |text: &str| match parse_transfer(text, &Extable::default()) {
+            Ok(Obj::HexString(HexString(nibs))) => {
+                assert_eq!(nibs.len(), 16, "{}", text);
+                Some(nibs.iter().rev().fold(0u64, |acc, n| (acc << 4) | *n as u64))
+            }
+            _ => None,
+        };
+        assert_eq!(value("# 1Fh"), Some(0x1f));
+        assert_eq!(value("# ABh"), Some(0xab));
+        assert_eq!(value("# 1Bh"), Some(0x1b));
+        assert_eq!(value("# 1b"), Some(1));
+        assert_eq!(value("# 101b"), Some(5));
+        assert_eq!(value("# 17o"), Some(15));
+        assert_eq!(value("# 99d"), Some(99));
+        assert_eq!(value("# 99"), Some(99));
+        assert_eq!(value("# FFFFFFFFFFFFFFFFh"), Some(u64::MAX));
+        // uppercase letters are digits, not suffixes
+        assert_eq!(value("# 1B"), None);
+        assert_eq!(value("# 1H"), None);
+        assert_eq!(value("# 1bh"), None);
+        assert_eq!(value("# 12b"), None);
+        assert_eq!(value("# 8o"), None);
+        assert_eq!(value("# 10000000000000000h"), None);
+    }
+}
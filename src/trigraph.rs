@@ -0,0 +1,174 @@
+//! Character translation of text transfers
+//!
+//! The `T()` field of the `%%HP:` header tells which characters are escaped:
+//! T(0) none, T(1) only line ends are written CR LF, T(2) also characters
+//! 128-159, T(3) also characters 160-255. Escaped characters are written as
+//! trigraphs, e.g. `\->` for `→`, or as `\NNN` with their decimal code.
+//! Backslashes that do not start an escape are left to string literals.
+use crate::decompile::Decompiled;
+use crate::transfer::TransferHeader;
+use crate::{charset, Extable, Obj};
+
+const TRIGRAPHS: &[(u8, &str)] = &[
+    (128, "<)"),
+    (129, "x-"),
+    (130, ".V"),
+    (131, "v/"),
+    (132, ".S"),
+    (133, "GS"),
+    (134, "|>"),
+    (135, "pi"),
+    (136, ".d"),
+    (137, "<="),
+    (138, ">="),
+    (139, "=/"),
+    (140, "Ga"),
+    (141, "->"),
+    (142, "<-"),
+    (143, "|v"),
+    (144, "|^"),
+    (145, "Gg"),
+    (146, "Gd"),
+    (147, "Ge"),
+    (148, "Gn"),
+    (149, "Gh"),
+    (150, "Gl"),
+    (151, "Gr"),
+    (152, "Gs"),
+    (153, "Gt"),
+    (154, "Gw"),
+    (155, "GD"),
+    (156, "PI"),
+    (157, "GW"),
+    (158, "[]"),
+    (159, "oo"),
+    (171, "<<"),
+    (176, "^o"),
+    (181, "Gm"),
+    (187, ">>"),
+    (215, ".x"),
+    (216, "O/"),
+    (223, "Gb"),
+    (247, ":-"),
+];
+
+fn escaped(code: u8, translation: u8) -> bool {
+    match translation {
+        2 => (128..160).contains(&code),
+        3 => code >= 128,
+        _ => false,
+    }
+}
+
+/// Escape the characters of the translation mode
+pub fn translate(text: &str, translation: u8) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        // x̄ is two unicode chars, but a single calculator character
+        let code = if c == 'x' && chars.peek() == Some(&'\u{0305}') && escaped(129, translation) {
+            chars.next();
+            Some(129)
+        } else {
+            charset::unicode_to_char(c)
+        };
+        match code {
+            Some(code) if escaped(code, translation) => match TRIGRAPHS.iter().find(|(n, _)| *n == code) {
+                Some((_, t)) => {
+                    out.push('\\');
+                    out.push_str(t);
+                }
+                None => out.push_str(&format!("\\{:03}", code)),
+            },
+            _ if c == '\n' && translation >= 1 => out.push_str("\r\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replace the escapes of the translation mode by their characters
+pub fn untranslate(text: &str, translation: u8) -> String {
+    let text = if translation >= 1 { text.replace("\r\n", "\n") } else { text.to_string() };
+    if translation < 2 {
+        return text;
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let next: String = chars[i + 1..chars.len().min(i + 4)].iter().collect();
+        let digits = next.len() == 3 && next.chars().all(|c| c.is_ascii_digit());
+        if let Some(code) = next.parse::<u8>().ok().filter(|_| digits) {
+            out.push_str(&charset::decode(&[code]));
+            i += 4;
+        } else if let Some((code, _)) = TRIGRAPHS.iter().find(|(_, t)| next.starts_with(t)) {
+            out.push_str(&charset::decode(&[*code]));
+            i += 3;
+        } else if i + 1 < chars.len() {
+            // \\ and \" belong to strings
+            out.push(chars[i]);
+            out.push(chars[i + 1]);
+            i += 2;
+        } else {
+            out.push(chars[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Text transfer of an object: the header line, then the object escaped for the
+/// translation mode, in the calculator charset
+pub fn write_transfer(obj: &Obj, extable: &Extable, translation: u8) -> Vec<u8> {
+    let header = TransferHeader { translation, ..TransferHeader::default() };
+    let text = format!("{}\n{}\n", header, obj.decompile(extable));
+    charset::encode(&translate(&text, translation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::parse_transfer;
+
+    #[test]
+    fn test_trigraphs() {
+        assert_eq!(translate("« → a 'Σ(I=1,N,I)≤π' »", 3), "\\<< \\-> a '\\GS(I=1,N,I)\\<=\\pi' \\>>");
+        assert_eq!(translate("« a »", 2), "« a »");
+        assert_eq!(translate("→\n", 1), "→\r\n");
+        assert_eq!(translate("é", 3), "\\233");
+        assert_eq!(untranslate("\\<< \\-> a \\>>", 3), "« → a »");
+        assert_eq!(untranslate("\\<< \\-> a \\>>", 0), "\\<< \\-> a \\>>");
+        assert_eq!(untranslate("\"a\\\"\\\\\" \\233", 3), "\"a\\\"\\\\\" é");
+    }
+
+    #[test]
+    fn test_all_chars_roundtrip() {
+        let text = charset::decode(&(1..=255).collect::<Vec<u8>>());
+        for translation in 0..=3 {
+            let translated = translate(&text, translation);
+            assert_eq!(untranslate(&translated, translation), text, "T({})", translation);
+        }
+        // only 127 is left outside ASCII
+        let translated = translate(&text, 3);
+        assert!(translated.chars().all(|c| c.is_ascii() || c == '\u{2592}'));
+    }
+
+    #[test]
+    fn test_write_transfer() {
+        let mut extable = Extable::default();
+        for (addr, name) in [(1, "x<<"), (2, "x>>"), (3, "x>>ABND"), (4, "xRPN->"), (5, "x+")] {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        let obj = parse_transfer("%%HP: T(3)A(R)F(.);\n\\<< \\-> a b \\<< a b + \\>> \\>>", &extable).unwrap();
+        let bytes = write_transfer(&obj, &extable, 3);
+        assert_eq!(String::from_utf8(bytes.clone()).unwrap(), "%%HP: T(3)A(R)F(.);\r\n\\<< \\-> a b \\<< a b + \\>> \\>>\r\n");
+        assert_eq!(write_transfer(&obj, &extable, 0)[20..22], [171, b' ']);
+    }
+}
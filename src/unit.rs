@@ -0,0 +1,162 @@
+//! Unit objects
+//!
+//! A unit is stored as an RPN token stream: the value, then the unit expression
+//! built from unit names (strings) and the `umP` (prefix), `um*`, `um/`, `um^`
+//! operators, ended by `umEND`. For instance `9.81_m/s^2` is
+//! `9.81 "m" "s" 2. um^ um/ umEND`.
+use std::fmt;
+
+use crate::decompile::Decompiled;
+use crate::{Extable, Obj};
+
+/// Unit part of a unit object, e.g. `m/s^2`
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitExpr {
+    /// unit name, including its prefix
+    Name(String),
+    Mul(Box<UnitExpr>, Box<UnitExpr>),
+    Div(Box<UnitExpr>, Box<UnitExpr>),
+    /// unit raised to a power, the exponent as displayed
+    Pow(Box<UnitExpr>, String),
+}
+
+/// Unit object rebuilt from its tokens
+#[derive(Debug, Clone)]
+pub struct Unit<'a> {
+    pub value: &'a Obj,
+    pub unit: UnitExpr,
+}
+
+impl<'a> Unit<'a> {
+    /// rebuild the unit expression, the operators are recognized by their extable names
+    pub fn from_tokens(tokens: &'a [Obj], extable: &Extable) -> Option<Self> {
+        let (value, tokens) = tokens.split_first()?;
+        enum Item<'b> {
+            Unit(UnitExpr),
+            Number(&'b Obj),
+        }
+        let mut stack: Vec<Item> = Vec::new();
+        for token in tokens {
+            match token {
+                Obj::CStr(s) => stack.push(Item::Unit(UnitExpr::Name(s.0.clone()))),
+                Obj::GlobalName(s) => stack.push(Item::Unit(UnitExpr::Name(s.clone()))),
+                Obj::Real(_) | Obj::Int(_) => stack.push(Item::Number(token)),
+                Obj::Ext(addr) => {
+                    let op = extable.addr_to_name.get(addr)?.as_str();
+                    if op == "umEND" {
+                        break;
+                    }
+                    let rhs = stack.pop()?;
+                    let lhs = stack.pop()?;
+                    let expr = match (op, lhs, rhs) {
+                        ("umP", Item::Unit(UnitExpr::Name(p)), Item::Unit(UnitExpr::Name(n))) => {
+                            UnitExpr::Name(p + &n)
+                        }
+                        ("um*", Item::Unit(a), Item::Unit(b)) => UnitExpr::Mul(Box::new(a), Box::new(b)),
+                        ("um/", Item::Unit(a), Item::Unit(b)) => UnitExpr::Div(Box::new(a), Box::new(b)),
+                        ("um^", Item::Unit(a), Item::Number(n)) => {
+                            let e = n.decompile(extable);
+                            let e = e.strip_suffix('.').map(str::to_string).unwrap_or(e);
+                            UnitExpr::Pow(Box::new(a), e)
+                        }
+                        _ => return None,
+                    };
+                    stack.push(Item::Unit(expr));
+                }
+                _ => return None,
+            }
+        }
+        match (stack.pop(), stack.is_empty()) {
+            (Some(Item::Unit(unit)), true) => Some(Unit { value, unit }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UnitExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // ^ binds tighter than * and /, which are left associative
+        match self {
+            UnitExpr::Name(n) => write!(f, "{}", n),
+            UnitExpr::Mul(a, b) => match **b {
+                UnitExpr::Div(..) => write!(f, "{}*({})", a, b),
+                _ => write!(f, "{}*{}", a, b),
+            },
+            UnitExpr::Div(a, b) => match **b {
+                UnitExpr::Mul(..) | UnitExpr::Div(..) => write!(f, "{}/({})", a, b),
+                _ => write!(f, "{}/{}", a, b),
+            },
+            UnitExpr::Pow(a, e) => match **a {
+                UnitExpr::Name(_) => write!(f, "{}^{}", a, e),
+                _ => write!(f, "({})^{}", a, e),
+            },
+        }
+    }
+}
+
+impl Decompiled for Unit<'_> {
+    fn decompile(&self, extable: &Extable) -> String {
+        format!("{}_{}", self.value.decompile(extable), self.unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Real, StringBlob};
+
+    fn extable() -> Extable {
+        let mut extable = Extable::default();
+        for (name, addr) in [("umP", 0x2d763), ("um*", 0x2d759), ("um/", 0x2d777), ("um^", 0x29cb9), ("umEND", 0x2d76d)] {
+            extable.name_to_addr.insert(name.to_string(), addr);
+            extable.addr_to_name.insert(addr, name.to_string());
+        }
+        extable
+    }
+    fn op(name: &str) -> Obj {
+        Obj::Ext(extable().name_to_addr[name])
+    }
+    fn name(n: &str) -> Obj {
+        Obj::CStr(StringBlob(n.to_string()))
+    }
+    fn real(v: f64) -> Obj {
+        Obj::Real(Real::from_float(v))
+    }
+
+    #[test]
+    fn test_acceleration() {
+        let tokens = vec![real(9.81), name("m"), name("s"), real(2.0), op("um^"), op("um/"), op("umEND")];
+        assert_eq!(Obj::Unit(tokens).decompile(&extable()), "9.81_m/s^2");
+    }
+
+    #[test]
+    fn test_prefix_and_grouping() {
+        // 1_kg*m^2/(s*K)
+        let tokens = vec![
+            real(1.0),
+            name("k"),
+            name("g"),
+            op("umP"),
+            name("m"),
+            real(2.0),
+            op("um^"),
+            op("um*"),
+            name("s"),
+            name("K"),
+            op("um*"),
+            op("um/"),
+            op("umEND"),
+        ];
+        assert_eq!(Obj::Unit(tokens).decompile(&extable()), "1._kg*m^2/(s*K)");
+        let tokens = vec![real(3.0), name("s"), real(-1.0), op("um^"), op("umEND")];
+        assert_eq!(Obj::Unit(tokens).decompile(&extable()), "3._s^-1");
+    }
+
+    #[test]
+    fn test_unknown_operators() {
+        let tokens = vec![real(1.0), name("m"), op("umEND")];
+        assert!(Unit::from_tokens(&tokens, &Extable::default()).is_none());
+        let tokens = vec![real(1.0), name("m"), name("s")];
+        assert!(Unit::from_tokens(&tokens, &extable()).is_none());
+    }
+}